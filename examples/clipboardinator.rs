@@ -36,7 +36,10 @@ async fn main() {
         copy_queue
             // remove it if it's some
             .drain_filter(|waiter| {
-                if waiter.try_get().is_some() {
+                if let Some(res) = waiter.try_get() {
+                    if let Err(oh_no) = res {
+                        console_log!("{}", oh_no);
+                    }
                     save = true;
                     // kill this
                     true
@@ -52,8 +55,13 @@ async fn main() {
         paste_queue
             .drain_filter(|waiter| {
                 if let Some(copied) = waiter.try_get() {
-                    strings.push(copied);
-                    save = true;
+                    match copied {
+                        Ok(copied) => {
+                            strings.push(copied);
+                            save = true;
+                        }
+                        Err(oh_no) => console_log!("{}", oh_no),
+                    }
                     true
                 } else {
                     if is_key_pressed(KeyCode::Q) {
@@ -15,6 +15,9 @@ params_register_js_plugin = function (importObject) {
     importObject.env.as_string = function (obj) {
         return js_object(get_js_object(obj).toString());
     }
+    importObject.env.as_f64 = function (obj) {
+        return Number(get_js_object(obj));
+    }
     // we need a seperate function for each primitive type
     let primitives = [
         ["u8", "number"],
@@ -94,47 +97,360 @@ params_register_js_plugin = function (importObject) {
 
 
     // === Storage ===
-    importObject.env.storage_save = function (key, val) {
+    function get_storage(session) {
+        return session ? sessionStorage : localStorage;
+    }
+    importObject.env.storage_save = function (session, key, val) {
         try {
             key = get_js_object(key);
             val = get_js_object(val);
-            localStorage.setItem(key, val);
+            get_storage(session).setItem(key, val);
             return js_object({
                 ok: null
             });
         } catch (e) {
             return js_object({
-                err: "Couldn't save to localstorage: " + e.toString()
+                err: "Couldn't save to storage: " + e.toString()
             });
         }
     }
-    importObject.env.storage_load = function (key) {
+    importObject.env.storage_load = function (session, key) {
         try {
             key = get_js_object(key);
-            let found = localStorage.getItem(key);
+            // null if it's not there
+            return js_object({
+                ok: get_storage(session).getItem(key)
+            });
+        } catch (e) {
+            return js_object({
+                err: "Couldn't load from storage: " + e.toString()
+            });
+        }
+    }
+    importObject.env.storage_remove = function (session, key) {
+        try {
+            get_storage(session).removeItem(get_js_object(key));
+            return js_object({
+                ok: null
+            });
+        } catch (e) {
+            return js_object({
+                err: "Couldn't remove from storage: " + e.toString()
+            });
+        }
+    }
 
-            let out = (found === null) ? {
-                err: `Couldn't find key \`${key}\` in localstorage`
-            } : {
-                ok: found
-            };
-            return js_object(out);
+    importObject.env.storage_usage = function (prefix) {
+        try {
+            prefix = get_js_object(prefix);
+            let total = 0;
+            for (let i = 0; i < localStorage.length; i++) {
+                let key = localStorage.key(i);
+                if (key.startsWith(prefix)) {
+                    total += key.length + localStorage.getItem(key).length;
+                }
+            }
+            return js_object({
+                ok: total
+            });
         } catch (e) {
             return js_object({
-                err: "Couldn't load from localstorage: " + e.toString()
+                err: "Couldn't measure localstorage: " + e.toString()
             });
         }
     }
+    importObject.env.storage_estimate = function () {
+        if (navigator.storage === undefined || navigator.storage.estimate === undefined) {
+            // no estimate for you
+            return js_object({
+                waiting: false,
+                value: null
+            });
+        }
+        let promise = navigator.storage.estimate().then((est) => ({
+            usage: est.usage,
+            quota: est.quota
+        }));
+        return js_object(waitify(promise));
+    }
+    importObject.env.random_u32 = function () {
+        if (window.crypto !== undefined && window.crypto.getRandomValues !== undefined) {
+            return window.crypto.getRandomValues(new Uint32Array(1))[0];
+        }
+        return Math.floor(Math.random() * 4294967296);
+    }
+
+    importObject.env.storage_persist = function () {
+        if (navigator.storage === undefined || navigator.storage.persist === undefined) {
+            // can't ask, so it's as persistent as it's going to get
+            return importObject.env.storage_persisted();
+        }
+        return js_object(waitify(navigator.storage.persist()));
+    }
+    importObject.env.storage_persisted = function () {
+        if (navigator.storage === undefined || navigator.storage.persisted === undefined) {
+            return js_object({
+                waiting: false,
+                value: false
+            });
+        }
+        return js_object(waitify(navigator.storage.persisted()));
+    }
+
+    // === Settings ===
+    importObject.env.settings_query = function () {
+        return js_object(window.location.search);
+    }
+
+    // === High scores ===
+    importObject.env.date_now = function () {
+        return Date.now();
+    }
+
+    // === Files ===
+    importObject.env.file_download = function (name, data) {
+        try {
+            name = get_js_object(name);
+            data = get_js_object(data);
+            let url = URL.createObjectURL(new Blob([data], {
+                type: "application/octet-stream"
+            }));
+
+            let link = document.createElement("a");
+            link.href = url;
+            link.download = name;
+            link.style.display = "none";
+            document.body.appendChild(link);
+            link.click();
+            document.body.removeChild(link);
+            // the download has its own reference to the blob by now
+            setTimeout(() => URL.revokeObjectURL(url), 0);
+
+            return js_object({
+                ok: null
+            });
+        } catch (e) {
+            return js_object({
+                err: "Couldn't download file: " + e.toString()
+            });
+        }
+    }
+    importObject.env.file_upload = function (accept) {
+        let promise = new Promise((resolve) => {
+            let input = document.createElement("input");
+            input.type = "file";
+            input.accept = get_js_object(accept);
+            input.onchange = () => {
+                let file = input.files[0];
+                if (file === undefined) {
+                    resolve({
+                        err: "No file was picked"
+                    });
+                } else {
+                    file.arrayBuffer().then((buf) => resolve({
+                        ok: new Uint8Array(buf)
+                    }), (oh_no) => resolve({
+                        err: String(oh_no)
+                    }));
+                }
+            };
+            // newer browsers tell us when the player closes the picker
+            input.addEventListener("cancel", () => resolve({
+                err: "No file was picked"
+            }));
+            input.click();
+        });
+        return js_object(waitify(promise));
+    }
 
     // === Clipboard ===
+    // paste events hand us the text without asking for permission, so keep them until rust asks
+    let clipboard_events = [];
+    document.addEventListener("paste", (e) => {
+        let text = e.clipboardData ? e.clipboardData.getData("text/plain") : "";
+        if (text !== "") {
+            clipboard_events.push({
+                kind: "paste",
+                text: text
+            });
+        }
+    });
+    for (let kind of ["copy", "cut"]) {
+        document.addEventListener(kind, () => {
+            // that's just us copying, not the player
+            if (!clipboard_exec_copying) {
+                clipboard_events.push({
+                    kind: kind
+                });
+            }
+        });
+    }
+    let clipboard_focus_count = 0;
+    window.addEventListener("focus", () => {
+        clipboard_focus_count += 1;
+    });
+    importObject.env.clipboard_focus_count = function () {
+        return clipboard_focus_count;
+    }
+    importObject.env.clipboard_permission = function () {
+        if (navigator.clipboard === undefined || navigator.clipboard.readText === undefined) {
+            return js_object({
+                waiting: false,
+                value: "unsupported"
+            });
+        }
+        if (navigator.permissions === undefined) {
+            // we can read, but who knows if it'll ask
+            return js_object({
+                waiting: false,
+                value: "prompt"
+            });
+        }
+        // firefox doesn't know about clipboard-read and rejects, but it can still read after asking
+        let promise = navigator.permissions.query({
+            name: "clipboard-read"
+        }).then((status) => status.state, () => "prompt");
+        return js_object(waitify(promise));
+    }
+    importObject.env.clipboard_drain_events = function () {
+        let out = clipboard_events;
+        clipboard_events = [];
+        return js_object(out);
+    }
+
     importObject.env.clipboard_get = function () {
-        let waiter = waitify(navigator.clipboard.readText());
-        return js_object(waiter);
+        if (navigator.clipboard === undefined || navigator.clipboard.readText === undefined) {
+            return js_object(clipboard_unsupported());
+        }
+        let promise = navigator.clipboard.readText().then((text) => {
+            if (text === "") {
+                throw { name: "NotFoundError", message: "The clipboard is empty" };
+            }
+            return text;
+        });
+        return js_object(clipboard_waitify(promise));
     }
 
     importObject.env.clipboard_set = function (text) {
-        let waiter = waitify(navigator.clipboard.writeText(get_js_object(text)));
-        return js_object(waiter);
+        text = get_js_object(text);
+        if (navigator.clipboard === undefined || navigator.clipboard.writeText === undefined) {
+            // not on http pages, so do it the old way
+            return js_object(clipboard_exec_copy(text));
+        }
+        let promise = navigator.clipboard.writeText(text).then(() => "async");
+        return js_object(clipboard_waitify(promise));
+    }
+
+    importObject.env.clipboard_set_formats = function (formats) {
+        if (navigator.clipboard === undefined || navigator.clipboard.write === undefined || typeof ClipboardItem === "undefined") {
+            return js_object(clipboard_unsupported());
+        }
+        let promise;
+        try {
+            let blobs = {};
+            for (let [mime, data] of Object.entries(get_js_object(formats))) {
+                // browsers only take a few types as-is; everything else has to be a web custom format
+                let type = (mime === "text/plain" || mime === "text/html") ? mime : "web " + mime;
+                if (ClipboardItem.supports !== undefined && !ClipboardItem.supports(type)) {
+                    continue;
+                }
+                blobs[type] = new Blob([data], {
+                    type: mime
+                });
+            }
+            promise = navigator.clipboard.write([new ClipboardItem(blobs)]).then(() => null);
+        } catch (e) {
+            promise = Promise.reject(e);
+        }
+        return js_object(clipboard_waitify(promise));
+    }
+
+    importObject.env.clipboard_get_formats = function () {
+        if (navigator.clipboard === undefined || navigator.clipboard.read === undefined) {
+            return js_object(clipboard_unsupported());
+        }
+        let promise = navigator.clipboard.read().then((items) => {
+            let reads = [];
+            for (let item of items) {
+                for (let type of item.types) {
+                    let mime;
+                    if (type === "text/plain" || type === "text/html") {
+                        mime = type;
+                    } else if (type.startsWith("web ")) {
+                        mime = type.slice(4);
+                    } else {
+                        continue;
+                    }
+                    reads.push(item.getType(type).then((blob) => blob.text()).then((data) => ({
+                        mime: mime,
+                        data: data
+                    })));
+                }
+            }
+            return Promise.all(reads);
+        }).then((formats) => {
+            if (formats.length === 0) {
+                throw { name: "NotFoundError", message: "There's no text on the clipboard" };
+            }
+            return formats;
+        });
+        return js_object(clipboard_waitify(promise));
+    }
+
+    importObject.env.clipboard_get_image = function () {
+        if (navigator.clipboard === undefined || navigator.clipboard.read === undefined) {
+            return js_object(clipboard_unsupported());
+        }
+        let promise = navigator.clipboard.read().then((items) => {
+            for (let item of items) {
+                let type = item.types.find((t) => t.startsWith("image/"));
+                if (type !== undefined) {
+                    return item.getType(type);
+                }
+            }
+            throw { name: "NotFoundError", message: "There's no image on the clipboard" };
+        }).then(createImageBitmap).then((bitmap) => {
+            let canvas = document.createElement("canvas");
+            canvas.width = bitmap.width;
+            canvas.height = bitmap.height;
+            let ctx = canvas.getContext("2d");
+            ctx.drawImage(bitmap, 0, 0);
+            let data = ctx.getImageData(0, 0, bitmap.width, bitmap.height);
+            return {
+                width: data.width,
+                height: data.height,
+                rgba: new Uint8Array(data.data.buffer)
+            };
+        });
+        return js_object(clipboard_waitify(promise));
+    }
+
+    importObject.env.clipboard_set_image = function (width, height, rgba) {
+        if (navigator.clipboard === undefined || navigator.clipboard.write === undefined || typeof ClipboardItem === "undefined") {
+            return js_object(clipboard_unsupported());
+        }
+        let promise;
+        try {
+            let canvas = document.createElement("canvas");
+            canvas.width = width;
+            canvas.height = height;
+            let data = new ImageData(new Uint8ClampedArray(get_js_object(rgba)), width, height);
+            canvas.getContext("2d").putImageData(data, 0, 0);
+            // Safari wants the ClipboardItem made right away, so hand it a promise of the PNG
+            let png = new Promise((resolve, reject) => canvas.toBlob((blob) => {
+                if (blob === null) {
+                    reject(new Error("Couldn't encode the image as a PNG"));
+                } else {
+                    resolve(blob);
+                }
+            }, "image/png"));
+            promise = navigator.clipboard.write([new ClipboardItem({
+                "image/png": png
+            })]).then(() => null);
+        } catch (e) {
+            promise = Promise.reject(e);
+        }
+        return js_object(clipboard_waitify(promise));
     }
 
     importObject.env.console_log = function (obj) {
@@ -175,4 +491,98 @@ function waitify(promise) {
     );
 
     return out;
+}
+
+// Like waitify, but resolves to `{ok: val}` or `{err: {kind, message}}` instead of
+// waiting forever if the promise rejects.
+function clipboard_waitify(promise) {
+    return waitify(promise.then(
+        (val) => ({
+            ok: val
+        }),
+        (oh_no) => ({
+            err: clipboard_error(oh_no)
+        })
+    ));
+}
+
+function clipboard_error(oh_no) {
+    let name = (oh_no && oh_no.name) || "";
+    let kind;
+    if (name === "NotAllowedError" || name === "SecurityError") {
+        kind = "denied";
+    } else if (name === "NotFoundError" || name === "DataError") {
+        kind = "empty";
+    } else if (name === "NotSupportedError") {
+        kind = "unsupported";
+    } else {
+        kind = "backend";
+    }
+    return {
+        kind: kind,
+        message: String((oh_no && oh_no.message) || oh_no)
+    };
+}
+
+// Copy with a hidden textarea and execCommand. This is deprecated but it's all there is
+// when navigator.clipboard isn't.
+// execCommand("copy") fires a copy event, which shouldn't show up as the player copying
+let clipboard_exec_copying = false;
+
+function clipboard_exec_copy(text) {
+    let copied = false;
+    let message = "navigator.clipboard isn't available and execCommand('copy') didn't work";
+    let focused = document.activeElement;
+    let textarea = document.createElement("textarea");
+    textarea.value = text;
+    textarea.setAttribute("readonly", "");
+    textarea.style.position = "fixed";
+    textarea.style.top = "0";
+    textarea.style.left = "0";
+    textarea.style.opacity = "0";
+    document.body.appendChild(textarea);
+    clipboard_exec_copying = true;
+    try {
+        textarea.select();
+        copied = document.execCommand("copy");
+    } catch (e) {
+        message += ": " + e.toString();
+    } finally {
+        clipboard_exec_copying = false;
+    }
+    document.body.removeChild(textarea);
+    // give focus back to the canvas so the game keeps getting keys
+    if (focused !== null && focused.focus !== undefined) {
+        focused.focus();
+    }
+
+    let value;
+    if (copied) {
+        value = {
+            ok: "exec_command"
+        };
+    } else {
+        value = {
+            err: {
+                kind: "unsupported",
+                message: message
+            }
+        };
+    }
+    return {
+        waiting: false,
+        value: value
+    };
+}
+
+function clipboard_unsupported() {
+    return {
+        waiting: false,
+        value: {
+            err: {
+                kind: "unsupported",
+                message: "navigator.clipboard isn't available"
+            }
+        }
+    };
 }
\ No newline at end of file
@@ -0,0 +1,169 @@
+//! Achievements, unlocked and saved across play sessions.
+//!
+//! Declare each achievement as an [`Achievement`] with an ID and how much progress it takes to unlock,
+//! and then [`unlock`](Achievements::unlock) them or [`add_progress`](Achievements::add_progress) to them as the player plays.
+//! Every frame, [`poll_unlocked`](Achievements::poll_unlocked) to find out which ones just got unlocked,
+//! so you can show a toast or something.
+//!
+//! Changes aren't saved right away; call [`Achievements::flush`] every so often (or before quitting).
+//! When saving, whatever's already saved gets merged in first, keeping the most progress on each achievement.
+//! So if the game is open in two tabs (or two windows) and each one unlocks something different,
+//! both unlocks stick around.
+
+use crate::storage::{self, Location};
+
+use std::collections::{BTreeMap, VecDeque};
+
+/// An achievement the player can unlock.
+///
+/// These are meant to be `const`s, like `const BEAT_LEVEL_1: Achievement = Achievement::new("beat_level_1");`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Achievement {
+    /// The ID, which is what's saved. Don't change it once you've shipped!
+    pub id: &'static str,
+    /// How much progress it takes to unlock this.
+    pub goal: u32,
+}
+
+impl Achievement {
+    /// Make an achievement that is either locked or unlocked, with nothing in between.
+    pub const fn new(id: &'static str) -> Self {
+        Self { id, goal: 1 }
+    }
+
+    /// Make an achievement that unlocks once it has made `goal` progress,
+    /// like "defeat 100 enemies."
+    pub const fn with_goal(id: &'static str, goal: u32) -> Self {
+        Self { id, goal }
+    }
+}
+
+/// The player's progress on all their achievements.
+#[derive(Debug, Clone)]
+pub struct Achievements {
+    location: Location,
+    /// Progress for each ID, including ones this build doesn't know about
+    progress: BTreeMap<String, u32>,
+    /// Achievements unlocked since the last poll
+    events: VecDeque<Achievement>,
+    /// Whether `progress` has changed since it was loaded or saved
+    dirty: bool,
+}
+
+impl Achievements {
+    /// Load achievements from the default location: the default [`Location`] with the profile `"achievements"`.
+    pub fn load() -> anyhow::Result<Self> {
+        Self::load_from(Location::new("achievements"))
+    }
+
+    /// Load achievements from the given location.
+    ///
+    /// If nothing is saved there yet, everything starts locked.
+    pub fn load_from(location: Location) -> anyhow::Result<Self> {
+        let progress = load_progress(&location)?;
+        Ok(Self {
+            location,
+            progress,
+            events: VecDeque::new(),
+            dirty: false,
+        })
+    }
+
+    /// Unlock an achievement.
+    ///
+    /// If it wasn't already unlocked, it's queued up for [`Achievements::poll_unlocked`].
+    pub fn unlock(&mut self, achievement: &Achievement) {
+        self.set_progress(achievement, achievement.goal);
+    }
+
+    /// Add some progress to an achievement.
+    ///
+    /// If this pushes it over its goal, it's queued up for [`Achievements::poll_unlocked`].
+    pub fn add_progress(&mut self, achievement: &Achievement, amount: u32) {
+        let progress = self.progress(achievement).saturating_add(amount);
+        self.set_progress(achievement, progress);
+    }
+
+    /// Set the progress on an achievement, if it's more than it already has.
+    /// (Achievements never go backwards.)
+    ///
+    /// If this pushes it over its goal, it's queued up for [`Achievements::poll_unlocked`].
+    pub fn set_progress(&mut self, achievement: &Achievement, progress: u32) {
+        let old = self.progress(achievement);
+        if progress <= old {
+            return;
+        }
+        self.progress.insert(achievement.id.to_string(), progress);
+        self.dirty = true;
+
+        if old < achievement.goal && progress >= achievement.goal {
+            self.events.push_back(*achievement);
+        }
+    }
+
+    /// Get how much progress an achievement has.
+    pub fn progress(&self, achievement: &Achievement) -> u32 {
+        self.progress.get(achievement.id).copied().unwrap_or(0)
+    }
+
+    /// Check if an achievement is unlocked.
+    pub fn is_unlocked(&self, achievement: &Achievement) -> bool {
+        self.progress(achievement) >= achievement.goal
+    }
+
+    /// Get an achievement that was unlocked since the last time this was called, if there is one.
+    ///
+    /// Call this in a loop every frame to get all of them.
+    pub fn poll_unlocked(&mut self) -> Option<Achievement> {
+        self.events.pop_front()
+    }
+
+    /// Check if there are changes that haven't been saved yet.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Save the achievements, if anything changed since they were last loaded or saved.
+    ///
+    /// Whatever's saved already is merged in first, so this picks up anything another
+    /// session unlocked in the meantime (without queueing them up as new unlocks).
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        for (id, progress) in load_progress(&self.location)? {
+            let ours = self.progress.entry(id).or_insert(0);
+            *ours = (*ours).max(progress);
+        }
+        storage::save_to(write_file(&self.progress), &self.location)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+fn load_progress(location: &Location) -> anyhow::Result<BTreeMap<String, u32>> {
+    Ok(if storage::exists(location)? {
+        parse_file(&String::from_utf8_lossy(&storage::load_from(location)?))
+    } else {
+        BTreeMap::new()
+    })
+}
+
+/// The file is `id=progress` lines.
+fn write_file(progress: &BTreeMap<String, u32>) -> String {
+    let mut out = String::new();
+    for (id, progress) in progress {
+        out.push_str(&format!("{}={}\n", id, progress));
+    }
+    out
+}
+
+fn parse_file(file: &str) -> BTreeMap<String, u32> {
+    file.lines()
+        .filter_map(|line| {
+            let (id, progress) = line.rsplit_once('=')?;
+            Some((id.to_string(), progress.parse().ok()?))
+        })
+        .collect()
+}
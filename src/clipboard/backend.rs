@@ -0,0 +1,262 @@
+//! Swapping out where the clipboard functions actually put things.
+//!
+//! By default everything goes to the system clipboard. When there isn't one (like in headless CI,
+//! or on a Linux box with no display) you can use a clipboard that only lives inside the game,
+//! or a [`MockClipboard`] you control, so copy and paste can be tested.
+//!
+//! [`set_backend`] changes the backend for the whole game. In tests, use [`override_backend`] instead,
+//! which only changes it for the current thread, so tests running in parallel don't swap each other's mocks out.
+//!
+//! The in-process and mock backends only do text; the primary selection and images are
+//! [`ClipboardError::Unsupported`] with them.
+
+use super::{lock, ClipboardError, CopyPath};
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+/// Where the clipboard functions put things.
+#[derive(Debug, Clone)]
+pub enum Backend {
+    /// The real clipboard. This is the default.
+    System,
+    /// A clipboard that only this game can see, which goes away when the game exits.
+    InProcess,
+    /// A clipboard you control, for tests.
+    Mock(MockClipboard),
+}
+
+static BACKEND: Mutex<Backend> = Mutex::new(Backend::System);
+
+/// What's on the in-process clipboard.
+static IN_PROCESS: Mutex<Option<String>> = Mutex::new(None);
+
+thread_local! {
+    /// Takes priority over `BACKEND` on this thread
+    static OVERRIDE: RefCell<Option<Backend>> = const { RefCell::new(None) };
+}
+
+/// Use the given backend for every clipboard function from now on, on every thread.
+///
+/// Threads with an [`override_backend`] keep using their override.
+pub fn set_backend(backend: Backend) {
+    *lock(&BACKEND) = backend;
+}
+
+/// Use the given backend for clipboard functions called on this thread, until the returned guard is dropped.
+///
+/// When it's dropped, the thread goes back to whatever it was using before.
+/// This is meant for tests:
+///
+/// ```
+/// # use quad_wasmnastics::clipboard::backend::{override_backend, Backend, MockClipboard};
+/// let mock = MockClipboard::new();
+/// let _guard = override_backend(Backend::Mock(mock.clone()));
+/// // ... copy and paste things, and check on `mock` ...
+/// ```
+pub fn override_backend(backend: Backend) -> BackendOverride {
+    let previous = OVERRIDE.with(|it| it.replace(Some(backend)));
+    BackendOverride {
+        previous,
+        _not_send: PhantomData,
+    }
+}
+
+/// Get the backend that's being used on this thread.
+pub fn backend() -> Backend {
+    with_backend(Backend::clone)
+}
+
+/// Puts the thread's backend back when dropped. See [`override_backend`].
+#[derive(Debug)]
+#[must_use = "the override goes away as soon as this is dropped"]
+pub struct BackendOverride {
+    previous: Option<Backend>,
+    /// It has to be dropped on the thread it was made on
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for BackendOverride {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        OVERRIDE.with(|it| *it.borrow_mut() = previous);
+    }
+}
+
+fn with_backend<T>(f: impl FnOnce(&Backend) -> T) -> T {
+    OVERRIDE.with(|it| match &*it.borrow() {
+        Some(backend) => f(backend),
+        None => f(&lock(&BACKEND)),
+    })
+}
+
+/// A pretend clipboard, for tests.
+///
+/// Clones all share the same clipboard, so keep one around after passing it to [`set_backend`]
+/// to see what got copied and to decide what pastes return.
+#[derive(Debug, Clone, Default)]
+pub struct MockClipboard {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    contents: Option<String>,
+    gets: VecDeque<Result<String, ClipboardError>>,
+    sets: VecDeque<Result<(), ClipboardError>>,
+    copied: Vec<String>,
+}
+
+impl MockClipboard {
+    /// Make a new, empty mock clipboard.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Put some text on the clipboard, as if another program copied it.
+    pub fn put(&self, text: String) {
+        lock(&self.state).contents = Some(text);
+    }
+
+    /// Get what's on the clipboard.
+    pub fn contents(&self) -> Option<String> {
+        lock(&self.state).contents.clone()
+    }
+
+    /// Make the next read return this, instead of what's on the clipboard.
+    ///
+    /// These queue up, so you can script several reads in a row.
+    pub fn script_get(&self, result: Result<String, ClipboardError>) {
+        lock(&self.state).gets.push_back(result);
+    }
+
+    /// Make the next write return this. If it's an `Err`, the clipboard isn't changed.
+    ///
+    /// These queue up, so you can script several writes in a row.
+    pub fn script_set(&self, result: Result<(), ClipboardError>) {
+        lock(&self.state).sets.push_back(result);
+    }
+
+    /// Get everything that's been copied to this clipboard (successfully), oldest first.
+    pub fn copied(&self) -> Vec<String> {
+        lock(&self.state).copied.clone()
+    }
+
+    fn get(&self) -> Result<String, ClipboardError> {
+        let mut state = lock(&self.state);
+        match state.gets.pop_front() {
+            Some(it) => it,
+            None => state.contents.clone().ok_or(ClipboardError::Empty),
+        }
+    }
+
+    fn set(&self, text: &str) -> Result<(), ClipboardError> {
+        let mut state = lock(&self.state);
+        state.sets.pop_front().unwrap_or(Ok(()))?;
+        state.contents = Some(text.to_string());
+        state.copied.push(text.to_string());
+        Ok(())
+    }
+}
+
+/// Check if we're using the real clipboard.
+pub(super) fn is_system() -> bool {
+    with_backend(|backend| matches!(backend, Backend::System))
+}
+
+/// Read text off the backend, or `None` if it's the system clipboard.
+pub(super) fn fake_get() -> Option<Result<String, ClipboardError>> {
+    with_backend(|backend| match backend {
+        Backend::System => None,
+        Backend::InProcess => Some(
+            lock(&IN_PROCESS)
+                .clone()
+                .filter(|it| !it.is_empty())
+                .ok_or(ClipboardError::Empty),
+        ),
+        Backend::Mock(mock) => Some(mock.get()),
+    })
+}
+
+/// Write text to the backend, or `None` if it's the system clipboard.
+pub(super) fn fake_set(text: &str) -> Option<Result<CopyPath, ClipboardError>> {
+    with_backend(|backend| match backend {
+        Backend::System => None,
+        Backend::InProcess => {
+            *lock(&IN_PROCESS) = Some(text.to_string());
+            Some(Ok(CopyPath::InProcess))
+        }
+        Backend::Mock(mock) => Some(mock.set(text).map(|()| CopyPath::Mock)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::{get_clipboard, set_clipboard};
+
+    #[test]
+    fn mock() {
+        let mock = MockClipboard::new();
+        let _guard = override_backend(Backend::Mock(mock.clone()));
+
+        assert_eq!(get_clipboard().try_get(), Some(Err(ClipboardError::Empty)));
+        mock.put(String::from("from another program"));
+        assert_eq!(
+            get_clipboard().try_get(),
+            Some(Ok(String::from("from another program")))
+        );
+
+        mock.script_get(Err(ClipboardError::Denied));
+        mock.script_set(Err(ClipboardError::Backend(String::from("oops"))));
+        assert_eq!(get_clipboard().try_get(), Some(Err(ClipboardError::Denied)));
+        assert!(matches!(
+            set_clipboard(String::from("lost")).try_get(),
+            Some(Err(ClipboardError::Backend(_)))
+        ));
+
+        assert_eq!(
+            set_clipboard(String::from("copied")).try_get(),
+            Some(Ok(CopyPath::Mock))
+        );
+        assert_eq!(get_clipboard().try_get(), Some(Ok(String::from("copied"))));
+        assert_eq!(mock.copied(), ["copied"]);
+    }
+
+    #[test]
+    fn overrides_are_per_thread() {
+        let threads: Vec<_> = (0..8)
+            .map(|idx| {
+                std::thread::spawn(move || {
+                    let mock = MockClipboard::new();
+                    let _guard = override_backend(Backend::Mock(mock.clone()));
+                    let expected: Vec<_> = (0..50).map(|n| format!("{}-{}", idx, n)).collect();
+                    for text in expected.iter() {
+                        set_clipboard(text.clone()).try_get().unwrap().unwrap();
+                        assert_eq!(get_clipboard().try_get(), Some(Ok(text.clone())));
+                    }
+                    assert_eq!(mock.copied(), expected);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn override_goes_away() {
+        let outer = MockClipboard::new();
+        let _outer = override_backend(Backend::Mock(outer.clone()));
+        {
+            let _inner = override_backend(Backend::Mock(MockClipboard::new()));
+            set_clipboard(String::from("inner")).try_get();
+        }
+        set_clipboard(String::from("outer")).try_get();
+        assert_eq!(outer.copied(), ["outer"]);
+    }
+}
@@ -0,0 +1,859 @@
+//! Cross-platform clipboard utilities, letting you save and load data from the clipboard
+//! on the fly.
+//!
+//! The windows implementation doesn't even segfault, Fedor!
+//!
+//! To use something other than the system clipboard (like in tests), see [`backend`].
+//! To copy your own data and recognize it when it's pasted back, see [`payload`].
+//! To notice when the clipboard changes, see [`watcher`].
+
+pub mod backend;
+pub mod payload;
+pub mod watcher;
+
+use crate::waiter::Waiter;
+
+use std::{
+    fmt,
+    sync::{Mutex, MutexGuard},
+};
+
+/// Something that went wrong talking to the clipboard.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClipboardError {
+    /// The player (or the browser) said no.
+    Denied,
+    /// There's no clipboard to talk to here.
+    Unsupported,
+    /// There's nothing on the clipboard, or nothing we can read as text.
+    Empty,
+    /// Something else went wrong in the clipboard backend. Here's what it said.
+    Backend(String),
+    /// The [`ClipboardImage`] given to [`set_clipboard_image`] didn't have `width * height * 4` bytes in it.
+    InvalidImage,
+}
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClipboardError::Denied => write!(f, "Permission to use the clipboard was denied"),
+            ClipboardError::Unsupported => write!(f, "The clipboard isn't supported here"),
+            ClipboardError::Empty => write!(f, "The clipboard is empty or doesn't have text on it"),
+            ClipboardError::Backend(e) => write!(f, "The clipboard backend failed: {}", e),
+            ClipboardError::InvalidImage => {
+                write!(f, "The image should have 4 bytes for each pixel")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClipboardError {}
+
+/// Nothing we do while holding these can leave them in a bad state, so ignore poisoning.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Try and get the string value off the clipboard.
+/// For images, see [`get_clipboard_image`].
+///
+/// Because the JS clipboard API is `async` for some horrid reason, returns a Waiter.
+/// On desktop, the clipboard is talked to on a worker thread (X11 can take its time), so it's a Waiter there too.
+/// It resolves to an `Err` if something goes wrong, instead of waiting forever.
+pub fn get_clipboard() -> Waiter<Result<String, ClipboardError>> {
+    if let Some(res) = backend::fake_get() {
+        return Waiter::new_immediate(res);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::get_clipboard()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        desktop::in_background(desktop::get_text)
+    }
+}
+
+/// How text made it onto the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CopyPath {
+    /// The web's async clipboard API.
+    AsyncClipboard,
+    /// The old `document.execCommand('copy')` trick, because the async clipboard API wasn't there.
+    /// (It isn't on pages that aren't served over HTTPS, like playtesting over a LAN.)
+    ExecCommand,
+    /// The desktop clipboard backend.
+    Desktop,
+    /// The in-process clipboard from [`backend::Backend::InProcess`].
+    InProcess,
+    /// A [`backend::MockClipboard`].
+    Mock,
+}
+
+/// Try and set the clipboard.
+///
+/// The returned `Waiter` will resolve to `Ok` saying how the text got there once its task is complete,
+/// or an `Err` if something went wrong.
+///
+/// On the web, if the async clipboard API isn't there, this falls back to `document.execCommand('copy')`
+/// with a hidden textarea. If that doesn't work either, you get an `Err`.
+pub fn set_clipboard(text: String) -> Waiter<Result<CopyPath, ClipboardError>> {
+    if let Some(res) = backend::fake_set(&text) {
+        return Waiter::new_immediate(res);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::set_clipboard(&text)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        desktop::in_background(|| desktop::set_text(text).map(|()| CopyPath::Desktop))
+    }
+}
+
+/// Which clipboard to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Selection {
+    /// The regular clipboard, which Ctrl+C and Ctrl+V use.
+    Clipboard,
+    /// The primary selection on Linux, which is whatever's selected and gets pasted with middle-click.
+    ///
+    /// This only exists on Linux desktops (through X11; Wayland sessions get it through XWayland).
+    /// Everywhere else you get [`ClipboardError::Unsupported`].
+    Primary,
+}
+
+/// Like [`get_clipboard`], but from the given clipboard.
+pub fn get_clipboard_from(selection: Selection) -> Waiter<Result<String, ClipboardError>> {
+    match selection {
+        Selection::Clipboard => get_clipboard(),
+        Selection::Primary if !backend::is_system() => {
+            Waiter::new_immediate(Err(ClipboardError::Unsupported))
+        }
+        Selection::Primary => {
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                desktop::in_background(desktop::get_primary)
+            }
+            #[cfg(target_arch = "wasm32")]
+            {
+                Waiter::new_immediate(Err(ClipboardError::Unsupported))
+            }
+        }
+    }
+}
+
+/// Like [`set_clipboard`], but to the given clipboard.
+pub fn set_clipboard_to(
+    selection: Selection,
+    text: String,
+) -> Waiter<Result<CopyPath, ClipboardError>> {
+    match selection {
+        Selection::Clipboard => set_clipboard(text),
+        Selection::Primary if !backend::is_system() => {
+            Waiter::new_immediate(Err(ClipboardError::Unsupported))
+        }
+        Selection::Primary => {
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                desktop::in_background(|| desktop::set_primary(text).map(|()| CopyPath::Desktop))
+            }
+            #[cfg(target_arch = "wasm32")]
+            {
+                let _ = text;
+                Waiter::new_immediate(Err(ClipboardError::Unsupported))
+            }
+        }
+    }
+}
+
+/// An image on the clipboard.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClipboardImage {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// The pixels, 4 bytes each (red, green, blue, alpha), row by row from the top left.
+    pub rgba: Vec<u8>,
+}
+
+/// Try and get an image off the clipboard.
+///
+/// On the web, this reads PNGs (or any other image the browser knows how to decode).
+/// On desktop, the clipboard backend only knows about text, so this is always [`ClipboardError::Unsupported`].
+pub fn get_clipboard_image() -> Waiter<Result<ClipboardImage, ClipboardError>> {
+    if !backend::is_system() {
+        return Waiter::new_immediate(Err(ClipboardError::Unsupported));
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::get_clipboard_image()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Waiter::new_immediate(Err(ClipboardError::Unsupported))
+    }
+}
+
+/// Try and put an image on the clipboard.
+///
+/// On the web, this writes it as a PNG.
+/// On desktop, the clipboard backend only knows about text, so this is always [`ClipboardError::Unsupported`].
+///
+/// If `image.rgba` isn't `width * height * 4` bytes long, you get [`ClipboardError::InvalidImage`].
+pub fn set_clipboard_image(image: &ClipboardImage) -> Waiter<Result<(), ClipboardError>> {
+    // usize is only 32 bits on the web, so this could overflow there
+    let expected = (image.width as u64)
+        .checked_mul(image.height as u64)
+        .and_then(|it| it.checked_mul(4));
+    if expected != Some(image.rgba.len() as u64) {
+        return Waiter::new_immediate(Err(ClipboardError::InvalidImage));
+    }
+    if !backend::is_system() {
+        return Waiter::new_immediate(Err(ClipboardError::Unsupported));
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::set_clipboard_image(image)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Waiter::new_immediate(Err(ClipboardError::Unsupported))
+    }
+}
+
+/// The same thing in several formats, to go on the clipboard all at once.
+///
+/// Whatever pastes it picks the best format it understands.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ClipboardFormats {
+    /// Plain text, for everything that doesn't understand the other formats.
+    pub text: String,
+    /// HTML, for things that understand formatting.
+    pub html: Option<String>,
+    /// Your own formats, as `(mime type, data)`, like `("application/x-my-game-level", ...)`.
+    pub custom: Vec<(String, String)>,
+}
+
+impl ClipboardFormats {
+    /// Make a set of formats with just plain text.
+    pub fn new(text: String) -> Self {
+        Self {
+            text,
+            html: None,
+            custom: Vec::new(),
+        }
+    }
+
+    /// Add HTML.
+    pub fn with_html(mut self, html: String) -> Self {
+        self.html = Some(html);
+        self
+    }
+
+    /// Add one of your own formats.
+    pub fn with_custom(mut self, mime: String, data: String) -> Self {
+        self.custom.push((mime, data));
+        self
+    }
+
+    /// Get the data for a MIME type, if it's here.
+    pub fn get(&self, mime: &str) -> Option<&str> {
+        match mime {
+            "text/plain" => Some(&self.text),
+            "text/html" => self.html.as_deref(),
+            _ => self
+                .custom
+                .iter()
+                .find(|(it, _)| it == mime)
+                .map(|(_, data)| data.as_str()),
+        }
+    }
+
+    /// List the MIME types that are here.
+    pub fn formats(&self) -> Vec<&str> {
+        let mut out = vec!["text/plain"];
+        if self.html.is_some() {
+            out.push("text/html");
+        }
+        out.extend(self.custom.iter().map(|(mime, _)| mime.as_str()));
+        out
+    }
+}
+
+/// Try and put several formats on the clipboard at once.
+///
+/// On the web, custom formats become [web custom formats](https://developer.chrome.com/blog/web-custom-formats-for-the-async-clipboard-api/)
+/// (with `"web "` in front of the MIME type) where the browser supports them, and are left out where it doesn't.
+/// On desktop, the clipboard backend only knows about text, so only `text` is copied.
+pub fn set_clipboard_formats(formats: &ClipboardFormats) -> Waiter<Result<(), ClipboardError>> {
+    if let Some(res) = backend::fake_set(&formats.text) {
+        return Waiter::new_immediate(res.map(|_| ()));
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::set_clipboard_formats(formats)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let text = formats.text.clone();
+        desktop::in_background(|| desktop::set_text(text))
+    }
+}
+
+/// Try and get every text-ish format off the clipboard.
+///
+/// Check [`ClipboardFormats::formats`] to see what you got.
+/// On desktop, you only ever get plain text.
+pub fn get_clipboard_formats() -> Waiter<Result<ClipboardFormats, ClipboardError>> {
+    if let Some(res) = backend::fake_get() {
+        return Waiter::new_immediate(res.map(ClipboardFormats::new));
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::get_clipboard_formats()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        desktop::in_background(|| desktop::get_text().map(ClipboardFormats::new))
+    }
+}
+
+/// Whether reading the clipboard will work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClipboardPermission {
+    /// Reading will work.
+    Granted,
+    /// Reading will ask the player first.
+    Prompt,
+    /// Reading won't work; the player (or the browser) said no.
+    Denied,
+    /// There's no clipboard to read.
+    Unsupported,
+}
+
+/// Find out if reading the clipboard will work, before you try.
+///
+/// Handy for deciding whether to show a "paste from clipboard" button.
+///
+/// On the web, this asks `navigator.permissions` about `clipboard-read`. Browsers that don't know about
+/// that permission (like Firefox) but can still read the clipboard say [`ClipboardPermission::Prompt`].
+/// On desktop, it's [`ClipboardPermission::Granted`] if the clipboard backend opens, and
+/// [`ClipboardPermission::Unsupported`] if it doesn't. The in-process and mock backends are always granted.
+pub fn read_permission() -> Waiter<ClipboardPermission> {
+    if !backend::is_system() {
+        return Waiter::new_immediate(ClipboardPermission::Granted);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::read_permission()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        desktop::in_background(|| match desktop::open() {
+            Ok(()) => ClipboardPermission::Granted,
+            Err(_) => ClipboardPermission::Unsupported,
+        })
+    }
+}
+
+/// Something the player did with the clipboard shortcuts.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClipboardEvent {
+    /// The player pasted this text.
+    Paste(String),
+    /// The player wants to copy something.
+    Copy,
+    /// The player wants to cut something.
+    Cut,
+}
+
+/// A clipboard shortcut, for [`press_shortcut`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shortcut {
+    /// Ctrl+C, or Cmd+C on Mac.
+    Copy,
+    /// Ctrl+X, or Cmd+X on Mac.
+    Cut,
+    /// Ctrl+V, or Cmd+V on Mac.
+    Paste,
+}
+
+/// Get everything the player did with the clipboard shortcuts since the last time this was called.
+///
+/// Call this every frame.
+///
+/// On the web, these come from the browser's `paste`, `copy` and `cut` events,
+/// so pasting doesn't need permission to read the clipboard (and works in Firefox).
+///
+/// On desktop, these come from [`press_shortcut`]. If you turn on the `macroquad` feature,
+/// this checks for Ctrl/Cmd+C/X/V (and Shift+Insert) itself. Pasted text is read off the clipboard backend
+/// in the background, so a paste shows up a frame or so after the shortcut.
+pub fn drain_clipboard_events() -> Vec<ClipboardEvent> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::drain_clipboard_events()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        desktop::drain_clipboard_events()
+    }
+}
+
+/// Tell us the player pressed a clipboard shortcut, so it shows up in [`drain_clipboard_events`].
+///
+/// This is for desktop, if you aren't using the `macroquad` feature (or have your own keybinds).
+/// On the web it does nothing, because the browser tells us itself.
+pub fn press_shortcut(shortcut: Shortcut) {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = shortcut;
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        desktop::press_shortcut(shortcut)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop {
+    use super::{backend, lock, ClipboardError, ClipboardEvent, Shortcut};
+    use crate::waiter::Waiter;
+
+    use copypasta::{ClipboardContext, ClipboardProvider};
+
+    use std::sync::{
+        mpsc::{self, Sender},
+        Mutex,
+    };
+
+    /// Events since the last drain.
+    static EVENTS: Mutex<Vec<ClipboardEvent>> = Mutex::new(Vec::new());
+
+    type Job = Box<dyn FnOnce() + Send>;
+
+    /// Sends jobs to the worker thread, which is started the first time it's needed.
+    ///
+    /// There's just the one thread so everything happens in the order it was asked for.
+    static WORKER: Mutex<Option<Sender<Job>>> = Mutex::new(None);
+
+    /// Do something on the worker thread, and get a Waiter for the result.
+    pub fn in_background<T: Send + 'static>(job: impl FnOnce() -> T + Send + 'static) -> Waiter<T> {
+        let (tx, rx) = mpsc::channel();
+        let job: Job = Box::new(move || {
+            // if the waiter was dropped nobody cares
+            let _ = tx.send(job());
+        });
+
+        let mut worker = lock(&WORKER);
+        if worker.is_none() {
+            let (tx, rx) = mpsc::channel::<Job>();
+            let spawned = std::thread::Builder::new()
+                .name(String::from("wasmnastics-clipboard"))
+                .spawn(move || {
+                    for job in rx {
+                        job();
+                    }
+                });
+            if spawned.is_ok() {
+                *worker = Some(tx);
+            }
+        }
+        let leftover = match worker.as_ref() {
+            Some(worker) => worker.send(job).err().map(|mpsc::SendError(job)| job),
+            None => Some(job),
+        };
+        drop(worker);
+        // No thread, so it'll have to be right here
+        if let Some(job) = leftover {
+            job();
+        }
+        Waiter::new_receiving(rx)
+    }
+
+    /// The clipboard, opened the first time it's needed and kept open until the game exits.
+    ///
+    /// On X11 whoever copied something has to stick around to hand it out, so if this got
+    /// dropped after every copy, the copied text would vanish from other programs.
+    /// Keeping it also means we don't open a new X connection every time.
+    static CLIPBOARD: Mutex<Option<ClipboardContext>> = Mutex::new(None);
+
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    ))]
+    type PrimaryContext =
+        copypasta::x11_clipboard::X11ClipboardContext<copypasta::x11_clipboard::Primary>;
+
+    /// Same as `CLIPBOARD`, but for the primary selection.
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    ))]
+    static PRIMARY: Mutex<Option<PrimaryContext>> = Mutex::new(None);
+
+    /// What copypasta returns; it doesn't export its own alias.
+    type BackendResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Run something with the provider in the slot, opening it first if need be.
+    ///
+    /// If opening it fails, it's tried again next time.
+    fn with_provider<P: ClipboardProvider, T>(
+        slot: &Mutex<Option<P>>,
+        open: fn() -> BackendResult<P>,
+        f: impl FnOnce(&mut P) -> BackendResult<T>,
+    ) -> Result<T, ClipboardError> {
+        let mut slot = lock(slot);
+        if slot.is_none() {
+            *slot = Some(open().map_err(|e| ClipboardError::Backend(e.to_string()))?);
+        }
+        let provider = slot.as_mut().unwrap();
+        f(provider).map_err(|e| ClipboardError::Backend(e.to_string()))
+    }
+
+    fn non_empty(text: String) -> Result<String, ClipboardError> {
+        if text.is_empty() {
+            Err(ClipboardError::Empty)
+        } else {
+            Ok(text)
+        }
+    }
+
+    /// Make sure the clipboard is open.
+    pub fn open() -> Result<(), ClipboardError> {
+        with_provider(&CLIPBOARD, ClipboardContext::new, |_| Ok(()))
+    }
+
+    pub fn get_text() -> Result<String, ClipboardError> {
+        with_provider(&CLIPBOARD, ClipboardContext::new, |it| it.get_contents()).and_then(non_empty)
+    }
+
+    pub fn set_text(text: String) -> Result<(), ClipboardError> {
+        with_provider(&CLIPBOARD, ClipboardContext::new, |it| {
+            it.set_contents(text)
+        })
+    }
+
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    ))]
+    pub fn get_primary() -> Result<String, ClipboardError> {
+        with_provider(&PRIMARY, PrimaryContext::new, |it| it.get_contents()).and_then(non_empty)
+    }
+
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    ))]
+    pub fn set_primary(text: String) -> Result<(), ClipboardError> {
+        with_provider(&PRIMARY, PrimaryContext::new, |it| it.set_contents(text))
+    }
+
+    /// Only Linux has a primary selection.
+    #[cfg(not(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    )))]
+    pub fn get_primary() -> Result<String, ClipboardError> {
+        Err(ClipboardError::Unsupported)
+    }
+
+    #[cfg(not(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "ios"))
+    )))]
+    pub fn set_primary(_text: String) -> Result<(), ClipboardError> {
+        Err(ClipboardError::Unsupported)
+    }
+
+    pub fn press_shortcut(shortcut: Shortcut) {
+        match shortcut {
+            Shortcut::Copy => lock(&EVENTS).push(ClipboardEvent::Copy),
+            Shortcut::Cut => lock(&EVENTS).push(ClipboardEvent::Cut),
+            Shortcut::Paste => {
+                if let Some(res) = backend::fake_get() {
+                    if let Ok(text) = res {
+                        lock(&EVENTS).push(ClipboardEvent::Paste(text));
+                    }
+                    return;
+                }
+                // Nobody needs the waiter; the event is the result
+                in_background(|| {
+                    // Nothing to paste, so nothing happened
+                    if let Ok(text) = get_text() {
+                        lock(&EVENTS).push(ClipboardEvent::Paste(text));
+                    }
+                });
+            }
+        }
+    }
+
+    pub fn drain_clipboard_events() -> Vec<ClipboardEvent> {
+        #[cfg(feature = "macroquad")]
+        detect_shortcuts();
+
+        std::mem::take(&mut *lock(&EVENTS))
+    }
+
+    #[cfg(feature = "macroquad")]
+    fn detect_shortcuts() {
+        use macroquad::input::{is_key_down, is_key_pressed, KeyCode};
+
+        let modifier = [
+            KeyCode::LeftControl,
+            KeyCode::RightControl,
+            KeyCode::LeftSuper,
+            KeyCode::RightSuper,
+        ]
+        .iter()
+        .any(|&key| is_key_down(key));
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+
+        if modifier && is_key_pressed(KeyCode::C) {
+            press_shortcut(Shortcut::Copy);
+        }
+        if modifier && is_key_pressed(KeyCode::X) {
+            press_shortcut(Shortcut::Cut);
+        }
+        if (modifier && is_key_pressed(KeyCode::V)) || (shift && is_key_pressed(KeyCode::Insert)) {
+            press_shortcut(Shortcut::Paste);
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use sapp_jsutils::{JsObject, JsObjectWeak};
+
+    use super::{
+        ClipboardError, ClipboardEvent, ClipboardFormats, ClipboardImage, ClipboardPermission,
+        CopyPath,
+    };
+    use crate::{
+        js_convert::{wrappers::Uint8Array, FromJsObject, ToJsObject},
+        objecttools::ObjectTools,
+        waiter::Waiter,
+    };
+
+    use anyhow::anyhow;
+
+    use std::collections::HashMap;
+
+    extern "C" {
+        /// These resolve to `{ok: ...}` or `{err: {kind, message}}`.
+        fn clipboard_get() -> JsObject;
+        fn clipboard_set(text: JsObjectWeak) -> JsObject;
+        fn clipboard_get_image() -> JsObject;
+        fn clipboard_set_image(width: u32, height: u32, rgba: JsObjectWeak) -> JsObject;
+        /// Takes an object of MIME types to data; resolves to an array of `{mime, data}`.
+        fn clipboard_set_formats(formats: JsObjectWeak) -> JsObject;
+        fn clipboard_get_formats() -> JsObject;
+        /// Returns an array of `{kind, text}` and empties the queue.
+        fn clipboard_drain_events() -> JsObject;
+        /// Resolves to `"granted"`, `"prompt"`, `"denied"` or `"unsupported"`.
+        fn clipboard_permission() -> JsObject;
+    }
+
+    pub fn get_clipboard() -> Waiter<Result<String, ClipboardError>> {
+        Waiter::new_waiting(unsafe { clipboard_get() })
+    }
+
+    pub fn set_clipboard(text: &str) -> Waiter<Result<CopyPath, ClipboardError>> {
+        let text = JsObject::string(&text);
+        Waiter::new_waiting(unsafe { clipboard_set(text.weak()) })
+    }
+
+    pub fn get_clipboard_image() -> Waiter<Result<ClipboardImage, ClipboardError>> {
+        Waiter::new_waiting(unsafe { clipboard_get_image() })
+    }
+
+    pub fn set_clipboard_image(image: &ClipboardImage) -> Waiter<Result<(), ClipboardError>> {
+        let rgba = JsObject::buffer(&image.rgba);
+        Waiter::new_waiting(unsafe { clipboard_set_image(image.width, image.height, rgba.weak()) })
+    }
+
+    pub fn set_clipboard_formats(formats: &ClipboardFormats) -> Waiter<Result<(), ClipboardError>> {
+        let mut map = HashMap::new();
+        map.insert("text/plain", formats.text.as_str());
+        if let Some(html) = &formats.html {
+            map.insert("text/html", html.as_str());
+        }
+        for (mime, data) in formats.custom.iter() {
+            map.insert(mime.as_str(), data.as_str());
+        }
+        match map.to_js() {
+            Ok(obj) => Waiter::new_waiting(unsafe { clipboard_set_formats(obj.weak()) }),
+            Err(e) => Waiter::new_immediate(Err(ClipboardError::Backend(e.to_string()))),
+        }
+    }
+
+    pub fn get_clipboard_formats() -> Waiter<Result<ClipboardFormats, ClipboardError>> {
+        Waiter::new_waiting(unsafe { clipboard_get_formats() })
+    }
+
+    pub fn drain_clipboard_events() -> Vec<ClipboardEvent> {
+        let events = unsafe { clipboard_drain_events() };
+        let res: anyhow::Result<Vec<ClipboardEvent>> = items(&events).and_then(|events| {
+            events
+                .into_iter()
+                .map(|event| {
+                    let kind = event
+                        .try_get_field("kind")
+                        .ok_or_else(|| anyhow!("Couldn't find `kind` field"))?;
+                    Ok(match String::from_js(kind)?.as_str() {
+                        "copy" => ClipboardEvent::Copy,
+                        "cut" => ClipboardEvent::Cut,
+                        _ => ClipboardEvent::Paste(
+                            event
+                                .try_get_field("text")
+                                .map(|it| it.as_string())
+                                .unwrap_or_default(),
+                        ),
+                    })
+                })
+                .collect()
+        });
+        // The JS side only ever makes well-formed events
+        res.unwrap_or_default()
+    }
+
+    /// `Vec<T>` doesn't impl FromJsObject yet, so get the items out of a JS array by hand.
+    fn items(array: &JsObject) -> anyhow::Result<Vec<JsObject>> {
+        let len = array
+            .try_get_field("length")
+            .ok_or_else(|| anyhow!("Couldn't find `length` field"))?;
+        (0..f64::from_js(len)? as usize)
+            .map(|idx| {
+                array
+                    .try_get_field(idx)
+                    .ok_or_else(|| anyhow!("Couldn't find item {}", idx))
+            })
+            .collect()
+    }
+
+    impl FromJsObject for ClipboardFormats {
+        type Error = anyhow::Error;
+
+        fn from_js(obj: JsObject) -> Result<Self, Self::Error> {
+            let mut out = ClipboardFormats::default();
+            for format in items(&obj)? {
+                let get = |field| -> anyhow::Result<String> {
+                    let it = format
+                        .try_get_field(field)
+                        .ok_or_else(|| anyhow!("Couldn't find `{}` field", field))?;
+                    Ok(String::from_js(it)?)
+                };
+                let (mime, data) = (get("mime")?, get("data")?);
+                match mime.as_str() {
+                    "text/plain" => out.text = data,
+                    "text/html" => out.html = Some(data),
+                    _ => out.custom.push((mime, data)),
+                }
+            }
+            Ok(out)
+        }
+    }
+
+    pub fn read_permission() -> Waiter<ClipboardPermission> {
+        Waiter::new_waiting(unsafe { clipboard_permission() })
+    }
+
+    impl FromJsObject for ClipboardPermission {
+        type Error = anyhow::Error;
+
+        fn from_js(obj: JsObject) -> Result<Self, Self::Error> {
+            match String::from_js(obj)?.as_str() {
+                "granted" => Ok(ClipboardPermission::Granted),
+                "prompt" => Ok(ClipboardPermission::Prompt),
+                "denied" => Ok(ClipboardPermission::Denied),
+                "unsupported" => Ok(ClipboardPermission::Unsupported),
+                other => Err(anyhow!("Unknown permission state {:?}", other)),
+            }
+        }
+    }
+
+    impl FromJsObject for CopyPath {
+        type Error = anyhow::Error;
+
+        fn from_js(obj: JsObject) -> Result<Self, Self::Error> {
+            match String::from_js(obj)?.as_str() {
+                "async" => Ok(CopyPath::AsyncClipboard),
+                "exec_command" => Ok(CopyPath::ExecCommand),
+                other => Err(anyhow!("Unknown copy path {:?}", other)),
+            }
+        }
+    }
+
+    impl FromJsObject for ClipboardImage {
+        type Error = anyhow::Error;
+
+        fn from_js(obj: JsObject) -> Result<Self, Self::Error> {
+            let get = |field| {
+                obj.try_get_field(field)
+                    .ok_or_else(|| anyhow!("Couldn't find `{}` field", field))
+            };
+            Ok(Self {
+                width: f64::from_js(get("width")?)? as u32,
+                height: f64::from_js(get("height")?)? as u32,
+                rgba: Uint8Array::from_js(get("rgba")?)?.0,
+            })
+        }
+    }
+
+    impl FromJsObject for ClipboardError {
+        type Error = anyhow::Error;
+
+        fn from_js(obj: JsObject) -> Result<Self, Self::Error> {
+            let kind = obj
+                .try_get_field("kind")
+                .ok_or_else(|| anyhow!("Couldn't find `kind` field"))?;
+            Ok(match String::from_js(kind)?.as_str() {
+                "denied" => ClipboardError::Denied,
+                "unsupported" => ClipboardError::Unsupported,
+                "empty" => ClipboardError::Empty,
+                _ => ClipboardError::Backend(
+                    obj.try_get_field("message")
+                        .map(|it| it.as_string())
+                        .unwrap_or_default(),
+                ),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_images() {
+        for (width, height, len) in [
+            (2, 2, 15),
+            (2, 2, 17),
+            (65536, 65536, 0),
+            (u32::MAX, u32::MAX, 0),
+        ]
+        .iter()
+        {
+            let image = ClipboardImage {
+                width: *width,
+                height: *height,
+                rgba: vec![0; *len],
+            };
+            assert_eq!(
+                set_clipboard_image(&image).try_get(),
+                Some(Err(ClipboardError::InvalidImage))
+            );
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Copying your own data as text, and telling it apart from whatever else gets pasted.
+//!
+//! Implement [`ClipboardPayload`] for the thing you want to copy, [`encode_payload`] it and put it on the clipboard,
+//! and then [`decode_payload`] whatever gets pasted. Since it's text, it survives going between windows,
+//! browser tabs, and chat programs.
+//!
+//! The text looks like `wasmnastics:my_game:entity:v1:H4sIAAAAAAAA...`: a marker, your app's tag,
+//! the type name, the version, and then the data gzipped and base64'd with [`zip64`].
+
+use crate::storage::flate::{unzip64, zip64};
+
+use anyhow::{anyhow, bail, Context};
+
+/// Things that can be copied with [`encode_payload`].
+pub trait ClipboardPayload: Sized {
+    /// The name of the type, so different kinds of things don't get pasted as each other.
+    const TYPE_NAME: &'static str;
+    /// The version of the format. Bump this when [`to_bytes`](ClipboardPayload::to_bytes) changes.
+    const VERSION: u32;
+
+    /// Turn this into bytes to copy.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Turn copied bytes back into a value.
+    ///
+    /// `version` is the version it was copied with, which might be older than [`VERSION`](ClipboardPayload::VERSION)
+    /// if it came from an older build.
+    fn from_bytes(bytes: &[u8], version: u32) -> anyhow::Result<Self>;
+}
+
+/// What got pasted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Pasted<T> {
+    /// One of ours!
+    Ours(T),
+    /// Just some text.
+    ForeignText(String),
+}
+
+/// Put at the front so we can spot our own text.
+const MARKER: &str = "wasmnastics";
+
+/// Turn a value into text to put on the clipboard.
+///
+/// `app` tags it as yours, so other games using this don't paste it by accident.
+/// It and the type name can't have `:` or whitespace in them.
+pub fn encode_payload<T: ClipboardPayload>(app: &str, value: &T) -> anyhow::Result<String> {
+    for tag in [app, T::TYPE_NAME].iter() {
+        if tag.is_empty() || tag.contains(|c: char| c == ':' || c.is_whitespace()) {
+            bail!(
+                "{:?} can't be used as a tag; it must be non-empty and have no `:` or whitespace",
+                tag
+            );
+        }
+    }
+    Ok(format!(
+        "{}:{}:{}:v{}:{}",
+        MARKER,
+        app,
+        T::TYPE_NAME,
+        T::VERSION,
+        zip64(value.to_bytes())?
+    ))
+}
+
+/// Figure out if some pasted text is one of ours.
+///
+/// If it's from this `app` and has the right type name, you get [`Pasted::Ours`].
+/// Anything else (including our own app's other types) is [`Pasted::ForeignText`].
+///
+/// It's an `Err` if it's tagged as ours but doesn't decode, like if it got cut off
+/// or is from a newer version than [`ClipboardPayload::VERSION`].
+pub fn decode_payload<T: ClipboardPayload>(app: &str, text: String) -> anyhow::Result<Pasted<T>> {
+    let mut parts = text.trim().splitn(5, ':');
+    let header = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    );
+    let (version, body) = match header {
+        (Some(MARKER), Some(their_app), Some(type_name), Some(version), Some(body))
+            if their_app == app && type_name == T::TYPE_NAME =>
+        {
+            (version, body)
+        }
+        _ => return Ok(Pasted::ForeignText(text)),
+    };
+
+    let version: u32 = version
+        .strip_prefix('v')
+        .and_then(|it| it.parse().ok())
+        .ok_or_else(|| anyhow!("Bad version {:?} on a pasted {}", version, T::TYPE_NAME))?;
+    if version > T::VERSION {
+        bail!(
+            "The pasted {} is version {}, but this only knows up to version {}",
+            T::TYPE_NAME,
+            version,
+            T::VERSION
+        );
+    }
+    let bytes =
+        unzip64(body).with_context(|| anyhow!("When unzipping a pasted {}", T::TYPE_NAME))?;
+    let value = T::from_bytes(&bytes, version)
+        .with_context(|| anyhow!("When reading a pasted {}", T::TYPE_NAME))?;
+    Ok(Pasted::Ours(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Level {
+        width: u8,
+        tiles: Vec<u8>,
+    }
+
+    impl ClipboardPayload for Level {
+        const TYPE_NAME: &'static str = "level";
+        const VERSION: u32 = 2;
+
+        fn to_bytes(&self) -> Vec<u8> {
+            let mut out = vec![self.width];
+            out.extend_from_slice(&self.tiles);
+            out
+        }
+
+        fn from_bytes(bytes: &[u8], version: u32) -> anyhow::Result<Self> {
+            match (version, bytes) {
+                // Version 1 didn't have a width
+                (1, tiles) => Ok(Level {
+                    width: 8,
+                    tiles: tiles.to_vec(),
+                }),
+                (_, [width, tiles @ ..]) => Ok(Level {
+                    width: *width,
+                    tiles: tiles.to_vec(),
+                }),
+                _ => bail!("No width"),
+            }
+        }
+    }
+
+    fn level() -> Level {
+        Level {
+            width: 3,
+            tiles: vec![0, 1, 2, 3, 4, 5],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = encode_payload("my_game", &level()).unwrap();
+        assert!(text.starts_with("wasmnastics:my_game:level:v2:"));
+        assert_eq!(
+            decode_payload::<Level>("my_game", text).unwrap(),
+            Pasted::Ours(level())
+        );
+    }
+
+    #[test]
+    fn older_version() {
+        let text = format!("wasmnastics:my_game:level:v1:{}", zip64([1, 2]).unwrap());
+        assert_eq!(
+            decode_payload::<Level>("my_game", text).unwrap(),
+            Pasted::Ours(Level {
+                width: 8,
+                tiles: vec![1, 2]
+            })
+        );
+    }
+
+    #[test]
+    fn foreign() {
+        let text = encode_payload("my_game", &level()).unwrap();
+        for foreign in [
+            String::from("just some text"),
+            String::from("wasmnastics:but:not:enough"),
+            text.replace("my_game", "other_game"),
+            text.replace(":level:", ":entity:"),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                decode_payload::<Level>("my_game", foreign.clone()).unwrap(),
+                Pasted::ForeignText(foreign.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn bad_payloads() {
+        let text = encode_payload("my_game", &level()).unwrap();
+        assert!(decode_payload::<Level>("my_game", text.replace(":v2:", ":v3:")).is_err());
+        assert!(decode_payload::<Level>("my_game", text.replace(":v2:", ":two:")).is_err());
+        assert!(decode_payload::<Level>("my_game", text[..text.len() - 6].to_string()).is_err());
+
+        assert!(encode_payload("my game", &level()).is_err());
+        assert!(encode_payload("my:game", &level()).is_err());
+        assert!(encode_payload("", &level()).is_err());
+    }
+}
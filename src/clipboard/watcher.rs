@@ -0,0 +1,240 @@
+//! Noticing when the clipboard changes, like for a "looks like you copied a level, import it?" prompt.
+//!
+//! Make a [`ClipboardWatcher`] and [`poll`](ClipboardWatcher::poll) it every frame. It reads the clipboard
+//! whenever the game gets focus back (on the web; on desktop, tell it with [`ClipboardWatcher::focus_regained`]),
+//! and every so often if you give it an interval.
+//!
+//! On the web, reading the clipboard can pop up a permission prompt, and nobody wants that every few seconds.
+//! So the watcher only reads if the player has already said yes, and does nothing otherwise.
+
+use super::{get_clipboard, ClipboardError};
+#[cfg(target_arch = "wasm32")]
+use super::{read_permission, ClipboardPermission};
+use crate::waiter::Waiter;
+
+/// Watches the clipboard for changes.
+#[derive(Debug)]
+pub struct ClipboardWatcher {
+    /// Seconds between reads, if it reads on a timer
+    interval: Option<f64>,
+    next_read: f64,
+    /// Whether to read next poll no matter what
+    read_now: bool,
+    /// What was on the clipboard last time, or `None` if we haven't looked yet.
+    /// (`Some(None)` means it was empty.)
+    last: Option<Option<String>>,
+    state: State,
+    #[cfg(target_arch = "wasm32")]
+    focus_count: u32,
+}
+
+#[derive(Debug)]
+enum State {
+    Idle,
+    /// Only on the web
+    #[cfg(target_arch = "wasm32")]
+    CheckingPermission(Waiter<ClipboardPermission>),
+    Reading(Waiter<Result<String, ClipboardError>>),
+}
+
+impl ClipboardWatcher {
+    /// Make a watcher that reads the clipboard when the game gets focus back.
+    ///
+    /// It reads once right away to see what's there, which doesn't count as a change.
+    pub fn new() -> Self {
+        Self {
+            interval: None,
+            next_read: 0.0,
+            read_now: true,
+            last: None,
+            state: State::Idle,
+            #[cfg(target_arch = "wasm32")]
+            focus_count: unsafe { wasm::clipboard_focus_count() },
+        }
+    }
+
+    /// Make a watcher that also reads the clipboard every `seconds` seconds.
+    pub fn with_interval(seconds: f64) -> Self {
+        Self {
+            interval: Some(seconds),
+            ..Self::new()
+        }
+    }
+
+    /// Tell the watcher the game just got focus back, so it reads the clipboard next poll.
+    ///
+    /// On the web this happens by itself.
+    pub fn focus_regained(&mut self) {
+        self.read_now = true;
+    }
+
+    /// Call this every frame. If the clipboard changed since last time it was read,
+    /// returns the new text.
+    pub fn poll(&mut self) -> Option<String> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let focus_count = unsafe { wasm::clipboard_focus_count() };
+            if focus_count != self.focus_count {
+                self.focus_count = focus_count;
+                self.read_now = true;
+            }
+        }
+
+        match &mut self.state {
+            State::Idle => {
+                let now = now();
+                let timer_up = self.interval.is_some() && now >= self.next_read;
+                if self.read_now || timer_up {
+                    self.read_now = false;
+                    if let Some(interval) = self.interval {
+                        self.next_read = now + interval;
+                    }
+                    self.state = start_read();
+                }
+                None
+            }
+            #[cfg(target_arch = "wasm32")]
+            State::CheckingPermission(waiter) => {
+                match waiter.try_get() {
+                    Some(ClipboardPermission::Granted) => {
+                        self.state = State::Reading(get_clipboard())
+                    }
+                    Some(_) => self.state = State::Idle,
+                    // It's never going to say, so ask again next time
+                    None if waiter.is_finished() => self.state = State::Idle,
+                    None => {}
+                }
+                None
+            }
+            State::Reading(waiter) => {
+                let res = match waiter.try_get() {
+                    Some(it) => it,
+                    None => {
+                        // It's never going to show up, so try again next time
+                        if waiter.is_finished() {
+                            self.state = State::Idle;
+                        }
+                        return None;
+                    }
+                };
+                self.state = State::Idle;
+                let current = match res {
+                    Ok(text) => Some(text),
+                    Err(ClipboardError::Empty) => None,
+                    // Try again next time
+                    Err(_) => return None,
+                };
+
+                let changed = match &self.last {
+                    Some(last) => last != &current,
+                    // The first look isn't a change
+                    None => false,
+                };
+                self.last = Some(current.clone());
+                if changed {
+                    current
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl Default for ClipboardWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn start_read() -> State {
+    // Desktop doesn't need to ask
+    #[cfg(target_arch = "wasm32")]
+    {
+        State::CheckingPermission(read_permission())
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        State::Reading(get_clipboard())
+    }
+}
+
+/// Seconds since the Unix epoch.
+///
+/// `SystemTime` panics on the web, so we have to ask JS there.
+fn now() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        unsafe { wasm::date_now() / 1000.0 }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|it| it.as_secs_f64())
+            .unwrap_or(0.0)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    extern "C" {
+        /// How many times the window has gotten focus.
+        pub fn clipboard_focus_count() -> u32;
+        /// `Date.now()`, in milliseconds.
+        pub fn date_now() -> f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::backend::{override_backend, Backend, MockClipboard};
+
+    #[test]
+    fn reports_changes() {
+        let mock = MockClipboard::new();
+        let _guard = override_backend(Backend::Mock(mock.clone()));
+        mock.put(String::from("already there"));
+
+        let mut watcher = ClipboardWatcher::new();
+        // The first look isn't a change
+        for _ in 0..3 {
+            assert_eq!(watcher.poll(), None);
+        }
+
+        mock.put(String::from("new"));
+        assert_eq!(watcher.poll(), None);
+        watcher.focus_regained();
+        let changed = (0..3).find_map(|_| watcher.poll());
+        assert_eq!(changed, Some(String::from("new")));
+
+        // Same thing again isn't a change
+        watcher.focus_regained();
+        assert_eq!((0..3).find_map(|_| watcher.poll()), None);
+    }
+
+    #[test]
+    fn recovers_from_dead_waiter() {
+        let mock = MockClipboard::new();
+        let _guard = override_backend(Backend::Mock(mock.clone()));
+        mock.put(String::from("before"));
+
+        let mut watcher = ClipboardWatcher::new();
+        watcher.poll();
+        watcher.poll();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        drop(tx);
+        watcher.state = State::Reading(Waiter::new_receiving(rx));
+        watcher.poll();
+        assert!(matches!(watcher.state, State::Idle));
+
+        mock.put(String::from("after"));
+        watcher.focus_regained();
+        assert_eq!(
+            (0..3).find_map(|_| watcher.poll()),
+            Some(String::from("after"))
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! Moving files in and out of the game, like for exporting and importing save files.
+//!
+//! On the web this triggers a browser download or opens a file picker.
+//! On desktop there aren't any dialogs; it just reads and writes the path you give it.
+
+use crate::waiter::Waiter;
+
+/// Give the player a file with the given data in it.
+///
+/// - On the web, this triggers a browser download with the given file name.
+/// - On desktop, this writes to the file name as a path. If it's relative, it goes
+///   in the player's Downloads directory (or their home directory if there isn't one).
+///
+/// Browsers might not allow this unless it happens soon after the player clicks or presses a key,
+/// so it's best to call this right when they press your "download my save" button.
+pub fn download<T: AsRef<[u8]>>(data: T, file_name: &str) -> anyhow::Result<()> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::download(data.as_ref(), file_name)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use anyhow::{anyhow, Context};
+
+        let path = desktop::path(file_name)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, data.as_ref())
+            .with_context(|| anyhow!("When writing to the file at {:?}", &path))
+    }
+}
+
+/// Get the contents of a file from the player.
+///
+/// - On the web, this opens a file picker. If the file name has an extension,
+///   the picker only offers files with that extension.
+/// - On desktop, this reads the file name as a path, resolved the same way as [`download`].
+///
+/// Browsers have the same restrictions on opening a picker as they do on [`download`].
+///
+/// If reading the file fails, or the player closes the file picker without choosing anything,
+/// the Waiter resolves to an `Err`. (Some older browsers don't say when the picker is closed,
+/// so there it'll never return Some.)
+pub fn upload(file_name: &str) -> Waiter<anyhow::Result<Vec<u8>>> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::upload(file_name)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use anyhow::{anyhow, Context};
+
+        let res = desktop::path(file_name).and_then(|path| {
+            std::fs::read(&path).with_context(|| anyhow!("When reading the file at {:?}", &path))
+        });
+        Waiter::new_immediate(res)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop {
+    use anyhow::anyhow;
+
+    use std::path::PathBuf;
+
+    /// Relative paths go in the Downloads directory, or home if that doesn't exist.
+    pub fn path(file_name: &str) -> anyhow::Result<PathBuf> {
+        let path = PathBuf::from(file_name);
+        if path.is_absolute() {
+            return Ok(path);
+        }
+        let root = dirs::download_dir()
+            .or_else(dirs::home_dir)
+            .ok_or_else(|| anyhow!("Couldn't find a downloads or home dir!"))?;
+        Ok(root.join(path))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use crate::{
+        js_convert::{wrappers::Uint8Array, FromJsObject},
+        waiter::Waiter,
+    };
+
+    use anyhow::{anyhow, Context};
+    use sapp_jsutils::{JsObject, JsObjectWeak};
+
+    extern "C" {
+        /// returns `Result<(), String>`
+        fn file_download(name: JsObjectWeak, data: JsObjectWeak) -> JsObject;
+        /// resolves to `Result<Uint8Array, String>`
+        fn file_upload(accept: JsObjectWeak) -> JsObject;
+    }
+
+    pub fn download(data: &[u8], file_name: &str) -> anyhow::Result<()> {
+        let name = JsObject::string(file_name);
+        let data = JsObject::buffer(data);
+
+        let result = unsafe { file_download(name.weak(), data.weak()) };
+        let result = Result::<(), String>::from_js(result)
+            .context("When trying to turn the returned value into a Result")?;
+        result.map_err(|e| anyhow!(e).context("When trying to download a file"))
+    }
+
+    pub fn upload(file_name: &str) -> Waiter<anyhow::Result<Vec<u8>>> {
+        let accept = match std::path::Path::new(file_name).extension() {
+            Some(ext) => format!(".{}", ext.to_string_lossy()),
+            None => String::new(),
+        };
+        let accept = JsObject::string(&accept);
+        Waiter::new_waiting_with(unsafe { file_upload(accept.weak()) }, |value| {
+            let result = Result::<Uint8Array, String>::from_js(value).map_err(|e| e.to_string())?;
+            Ok(result
+                .map(|it| it.0)
+                .map_err(|e| anyhow!(e).context("When reading the uploaded file")))
+        })
+    }
+}
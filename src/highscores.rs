@@ -0,0 +1,333 @@
+//! Local high-score tables, saved across play sessions.
+//!
+//! Each [`Board`] is a bounded, sorted list of [`Entry`]s saved to its own [`Location`],
+//! so you can have as many boards as you like (one per level, one per difficulty...)
+//!
+//! Entries can also be turned into short share codes, so players can brag to each other.
+
+use crate::storage::{
+    self,
+    flate::{unzip64, zip64},
+    Location,
+};
+
+use anyhow::{anyhow, bail, Context};
+
+/// Which way is up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Order {
+    /// Bigger scores are better, like points.
+    HigherIsBetter,
+    /// Smaller scores are better, like speedrun times.
+    LowerIsBetter,
+}
+
+/// One score on a board.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Entry {
+    /// The score.
+    pub score: i64,
+    /// The name of whoever got it, if you're keeping track.
+    pub name: Option<String>,
+    /// When it was gotten, in seconds since the Unix epoch, if you're keeping track.
+    pub timestamp: Option<u64>,
+}
+
+impl Entry {
+    /// Make an entry with just a score.
+    pub fn new(score: i64) -> Self {
+        Self {
+            score,
+            name: None,
+            timestamp: None,
+        }
+    }
+
+    /// Make an entry with a name, timestamped with the current time.
+    pub fn now(score: i64, name: Option<String>) -> Self {
+        Self {
+            score,
+            name,
+            timestamp: Some(now()),
+        }
+    }
+}
+
+/// A high-score table.
+#[derive(Debug, Clone)]
+pub struct Board {
+    name: String,
+    capacity: usize,
+    order: Order,
+    location: Location,
+    entries: Vec<Entry>,
+}
+
+impl Board {
+    /// Load a board with the given name from the default location:
+    /// the default [`Location`] with the profile `"highscores-{name}"`.
+    ///
+    /// If nothing is saved there yet, you get an empty board.
+    pub fn load(name: &str, capacity: usize, order: Order) -> anyhow::Result<Self> {
+        let location = Location::new(format!("highscores-{}", name));
+        Self::load_from(name, capacity, order, location)
+    }
+
+    /// Load a board with the given name from the given location.
+    ///
+    /// If nothing is saved there yet, you get an empty board.
+    /// If there are more entries saved than `capacity`, the worst ones are dropped.
+    pub fn load_from(
+        name: &str,
+        capacity: usize,
+        order: Order,
+        location: Location,
+    ) -> anyhow::Result<Self> {
+        let mut entries = if storage::exists(&location)? {
+            decode_board(&storage::load_from(&location)?)
+                .with_context(|| anyhow!("When reading the high-score board {:?}", name))?
+        } else {
+            Vec::new()
+        };
+
+        sort(&mut entries, order);
+        entries.truncate(capacity);
+        Ok(Self {
+            name: name.to_string(),
+            capacity,
+            order,
+            location,
+            entries,
+        })
+    }
+
+    /// Put an entry on the board and save it.
+    ///
+    /// Returns its rank (`0` is first place), or `None` if it didn't make the cut.
+    /// Ties go to whoever got there first.
+    pub fn insert(&mut self, entry: Entry) -> anyhow::Result<Option<usize>> {
+        let rank = match self.rank_of(entry.score) {
+            Some(it) => it,
+            None => return Ok(None),
+        };
+        self.entries.insert(rank, entry);
+        self.entries.truncate(self.capacity);
+        self.save()?;
+        Ok(Some(rank))
+    }
+
+    /// Find what rank a score would get if it were inserted now, or `None` if it wouldn't make the cut.
+    ///
+    /// Handy for deciding whether to ask the player for their name.
+    pub fn rank_of(&self, score: i64) -> Option<usize> {
+        let order = self.order;
+        let rank = self
+            .entries
+            .iter()
+            .position(|it| is_better(score, it.score, order))
+            .unwrap_or(self.entries.len());
+        if rank < self.capacity {
+            Some(rank)
+        } else {
+            None
+        }
+    }
+
+    /// Get all the entries, best first.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Get the name of this board.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the most entries this board will hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Remove every entry and save the empty board.
+    pub fn clear(&mut self) -> anyhow::Result<()> {
+        self.entries.clear();
+        self.save()
+    }
+
+    /// Save the board.
+    ///
+    /// [`Board::insert`] and [`Board::clear`] already do this, so you shouldn't need to.
+    pub fn save(&self) -> anyhow::Result<()> {
+        storage::save_to(encode_board(&self.entries), &self.location)
+    }
+
+    /// Turn one of this board's entries into a share code.
+    ///
+    /// Returns `None` if there's no entry with that rank.
+    pub fn share_code(&self, rank: usize) -> Option<anyhow::Result<String>> {
+        self.entries
+            .get(rank)
+            .map(|entry| share_code(&self.name, entry))
+    }
+}
+
+/// Turn an entry into a short string players can paste to each other.
+///
+/// The board name goes in too, so you can tell which board it's for.
+///
+/// Note anyone can make up a share code with whatever score they want in it!
+/// It's for bragging, not for proving anything.
+pub fn share_code(board_name: &str, entry: &Entry) -> anyhow::Result<String> {
+    let mut buf = vec![FORMAT_VERSION];
+    push_str(&mut buf, board_name);
+    encode_entry(&mut buf, entry);
+    zip64(buf)
+}
+
+/// Read a share code back into the board name and entry.
+pub fn parse_share_code(code: &str) -> anyhow::Result<(String, Entry)> {
+    let buf = unzip64(code.trim()).context("When reading the share code")?;
+    let mut reader = buf.as_slice();
+    check_version(&mut reader)?;
+    let board_name = read_str(&mut reader)?;
+    let entry = decode_entry(&mut reader)?;
+    Ok((board_name, entry))
+}
+
+fn is_better(score: i64, than: i64, order: Order) -> bool {
+    match order {
+        Order::HigherIsBetter => score > than,
+        Order::LowerIsBetter => score < than,
+    }
+}
+
+/// Stable, so ties stay in the order they were gotten
+fn sort(entries: &mut [Entry], order: Order) {
+    entries.sort_by(|a, b| match order {
+        Order::HigherIsBetter => b.score.cmp(&a.score),
+        Order::LowerIsBetter => a.score.cmp(&b.score),
+    });
+}
+
+/// Bump this if the format changes.
+const FORMAT_VERSION: u8 = 1;
+
+/// A board is the version, the entry count, and then each entry.
+fn encode_board(entries: &[Entry]) -> Vec<u8> {
+    let mut buf = vec![FORMAT_VERSION];
+    buf.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for entry in entries {
+        encode_entry(&mut buf, entry);
+    }
+    buf
+}
+
+fn decode_board(mut buf: &[u8]) -> anyhow::Result<Vec<Entry>> {
+    check_version(&mut buf)?;
+    let count = u32::from_le_bytes(take(&mut buf)?);
+    (0..count).map(|_| decode_entry(&mut buf)).collect()
+}
+
+/// An entry is the score, then the timestamp and name, each with a byte in front saying if they're there.
+fn encode_entry(buf: &mut Vec<u8>, entry: &Entry) {
+    buf.extend_from_slice(&entry.score.to_le_bytes());
+    match entry.timestamp {
+        Some(timestamp) => {
+            buf.push(1);
+            buf.extend_from_slice(&timestamp.to_le_bytes());
+        }
+        None => buf.push(0),
+    }
+    match &entry.name {
+        Some(name) => {
+            buf.push(1);
+            push_str(buf, name);
+        }
+        None => buf.push(0),
+    }
+}
+
+fn decode_entry(buf: &mut &[u8]) -> anyhow::Result<Entry> {
+    let score = i64::from_le_bytes(take(buf)?);
+    let [has_timestamp] = take(buf)?;
+    let timestamp = if has_timestamp != 0 {
+        Some(u64::from_le_bytes(take(buf)?))
+    } else {
+        None
+    };
+    let [has_name] = take(buf)?;
+    let name = if has_name != 0 {
+        Some(read_str(buf)?)
+    } else {
+        None
+    };
+    Ok(Entry {
+        score,
+        name,
+        timestamp,
+    })
+}
+
+fn check_version(buf: &mut &[u8]) -> anyhow::Result<()> {
+    let [version] = take(buf)?;
+    if version != FORMAT_VERSION {
+        bail!(
+            "Unknown high-score format version {} (expected {})",
+            version,
+            FORMAT_VERSION
+        );
+    }
+    Ok(())
+}
+
+fn push_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn read_str(buf: &mut &[u8]) -> anyhow::Result<String> {
+    let len = u32::from_le_bytes(take(buf)?) as usize;
+    if buf.len() < len {
+        bail!("Ran out of high-score data");
+    }
+    let (s, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(String::from_utf8(s.to_vec())?)
+}
+
+/// Take `N` bytes off the front of the buffer.
+fn take<const N: usize>(buf: &mut &[u8]) -> anyhow::Result<[u8; N]> {
+    if buf.len() < N {
+        bail!("Ran out of high-score data");
+    }
+    let (it, rest) = buf.split_at(N);
+    *buf = rest;
+    let mut out = [0; N];
+    out.copy_from_slice(it);
+    Ok(out)
+}
+
+/// Seconds since the Unix epoch.
+///
+/// `SystemTime` panics on the web, so we have to ask JS there.
+fn now() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        (unsafe { wasm::date_now() } / 1000.0) as u64
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|it| it.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    extern "C" {
+        /// `Date.now()`, in milliseconds.
+        pub fn date_now() -> f64;
+    }
+}
@@ -33,6 +33,36 @@ impl FromJsObject for String {
     }
 }
 
+/// Convert JS Numbers into f64s.
+impl FromJsObject for f64 {
+    type Error = BadJsTypeError;
+
+    fn from_js(obj: JsObject) -> Result<Self, Self::Error> {
+        let ty = obj.js_type();
+        if ty != JsType::Number {
+            Err(BadJsTypeError::new(vec![JsType::Number], ty))
+        } else {
+            Ok(obj.as_f64())
+        }
+    }
+}
+
+/// Convert JS Booleans into bools.
+///
+/// This is strict; truthy things that aren't `true` are an error.
+impl FromJsObject for bool {
+    type Error = BadJsTypeError;
+
+    fn from_js(obj: JsObject) -> Result<Self, Self::Error> {
+        let ty = obj.js_type();
+        if ty != JsType::Boolean {
+            Err(BadJsTypeError::new(vec![JsType::Boolean], ty))
+        } else {
+            Ok(obj.truthy())
+        }
+    }
+}
+
 /// mega difficult here
 impl FromJsObject for JsObject {
     type Error = Infallible;
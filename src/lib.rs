@@ -1,7 +1,11 @@
 #![doc = include_str!("../README.md")]
 
+pub mod achievements;
 pub mod clipboard;
+pub mod files;
+pub mod highscores;
 pub mod js_convert;
+pub mod settings;
 pub mod storage;
 pub mod waiter;
 
@@ -17,6 +17,8 @@ extern "C" {
     fn set_field_any(obj: JsObjectWeak, key: JsObjectWeak, val: JsObjectWeak);
     /// `.toString`
     fn as_string(obj: JsObjectWeak) -> JsObject;
+    /// `Number(obj)`
+    fn as_f64(obj: JsObjectWeak) -> f64;
     fn array() -> JsObject;
     fn try_get_field(obj: JsObjectWeak, key: JsObjectWeak) -> JsObject;
     /// Check if something `==` or `===` something else
@@ -51,6 +53,11 @@ pub trait ObjectTools {
     /// with a toString method that throws an exception, but why would you do that?)
     fn as_string(&self) -> String;
 
+    /// Convert this to a number using JS' `Number()` function.
+    ///
+    /// If it's not a number and can't be turned into one, this will be `NaN`.
+    fn as_f64(&self) -> f64;
+
     /// Set any string field of this to any value.
     /// This works with arrays and objects.
     fn set_field<K: ToJsObject, V: ToJsObject>(&mut self, key: K, val: V) -> anyhow::Result<()>;
@@ -125,6 +132,10 @@ impl ObjectTools for JsObject {
         stringed.to_string_direct()
     }
 
+    fn as_f64(&self) -> f64 {
+        unsafe { as_f64(self.weak()) }
+    }
+
     fn set_field<K: ToJsObject, V: ToJsObject>(&mut self, key: K, val: V) -> anyhow::Result<()> {
         let ty = self.js_type();
         if ty != JsType::Object {
@@ -0,0 +1,335 @@
+//! Persisted game settings, like volume, keybinds, and language.
+//!
+//! Declare each setting as a [`Key`] with a name and a default, and then [`Settings::get`] and [`Settings::set`] them.
+//! Values are looked up in three layers, from highest priority to lowest:
+//!
+//! 1. Overrides from the URL query (`?volume=0.5&fullscreen=true`) on the web, or command line
+//!    arguments (`--volume=0.5 --fullscreen=true`) on desktop. These are never saved.
+//! 2. The settings file, stored with [`storage`](crate::storage) at the profile `"settings"`.
+//! 3. The key's default.
+//!
+//! Setting a value doesn't save it right away; call [`Settings::flush`] every so often (or before quitting)
+//! and it'll save if anything changed.
+//!
+//! Keys the game doesn't know about are left alone when the file is saved again,
+//! so an older build of your game won't erase settings a newer one added.
+
+use crate::storage::{self, Location};
+
+use std::collections::BTreeMap;
+
+/// A setting with a name, a type, and a default value.
+///
+/// These are meant to be `const`s, like `const VOLUME: Key<f32> = Key::new("volume", 0.8);`.
+/// (`String` keys can't be `const`, so make them with a function instead.)
+#[derive(Debug, Clone)]
+pub struct Key<T> {
+    name: &'static str,
+    default: T,
+}
+
+impl<T> Key<T> {
+    /// Make a new key.
+    ///
+    /// The name should only have letters, numbers, `_` and `-` in it, so it works
+    /// in URLs and on the command line.
+    pub const fn new(name: &'static str, default: T) -> Self {
+        Self { name, default }
+    }
+
+    /// Get the name of this key.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Get the default value of this key.
+    pub fn default_value(&self) -> &T {
+        &self.default
+    }
+}
+
+/// Things that can be stored as a setting.
+///
+/// Each setting is stored as a string. If a stored string can't be turned back into
+/// a value, the key's default is used instead.
+pub trait SettingValue: Sized {
+    /// Turn this into a string to store.
+    fn to_setting(&self) -> String;
+
+    /// Try to turn a stored string back into a value.
+    fn from_setting(setting: &str) -> Option<Self>;
+}
+
+/// Anything that can go through `ToString` and `FromStr` can be a setting.
+macro_rules! impl_setting_value {
+    ($($implee:ty)*) => {
+        $(
+            impl SettingValue for $implee {
+                fn to_setting(&self) -> String {
+                    self.to_string()
+                }
+
+                fn from_setting(setting: &str) -> Option<Self> {
+                    setting.parse().ok()
+                }
+            }
+        )*
+    }
+}
+
+impl_setting_value!(u8 u16 u32 u64 usize i8 i16 i32 i64 isize f32 f64 bool char String);
+
+/// All the settings, layered together.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    location: Location,
+    /// What's in the file, including keys we don't know about
+    saved: BTreeMap<String, String>,
+    /// From the URL or command line; never saved
+    overrides: BTreeMap<String, String>,
+    /// Whether `saved` has changed since it was loaded or saved
+    dirty: bool,
+}
+
+impl Settings {
+    /// Load settings from the default location: the default [`Location`] with the profile `"settings"`.
+    pub fn load() -> anyhow::Result<Self> {
+        Self::load_from(Location::new("settings"))
+    }
+
+    /// Load settings from the given location.
+    ///
+    /// If nothing is saved there yet, you get all the defaults.
+    /// The overrides are read from the URL or command line here too.
+    pub fn load_from(location: Location) -> anyhow::Result<Self> {
+        let saved = if storage::exists(&location)? {
+            let data = storage::load_from(&location)?;
+            parse_file(&String::from_utf8_lossy(&data))
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            location,
+            saved,
+            overrides: overrides(),
+            dirty: false,
+        })
+    }
+
+    /// Get the value of a setting.
+    pub fn get<T: SettingValue + Clone>(&self, key: &Key<T>) -> T {
+        self.overrides
+            .get(key.name)
+            .and_then(|it| T::from_setting(it))
+            .or_else(|| self.saved.get(key.name).and_then(|it| T::from_setting(it)))
+            .unwrap_or_else(|| key.default.clone())
+    }
+
+    /// Set the value of a setting.
+    ///
+    /// This also drops any override for it, so the player sees the change they just made.
+    pub fn set<T: SettingValue>(&mut self, key: &Key<T>, value: T) {
+        self.overrides.remove(key.name);
+        let value = value.to_setting();
+        if self.saved.get(key.name) != Some(&value) {
+            self.saved.insert(key.name.to_string(), value);
+            self.dirty = true;
+        }
+    }
+
+    /// Put a setting back to its default.
+    pub fn reset<T>(&mut self, key: &Key<T>) {
+        self.overrides.remove(key.name);
+        if self.saved.remove(key.name).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    /// Check if there are changes that haven't been saved yet.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Save the settings, if anything changed since they were last loaded or saved.
+    ///
+    /// This is cheap to call when nothing's changed, so you can call it every frame if you like.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        if self.dirty {
+            storage::save_to(write_file(&self.saved), &self.location)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+/// The file is `key=value` lines, with backslashes and newlines in values escaped.
+fn write_file(settings: &BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+    for (key, value) in settings {
+        out.push_str(key);
+        out.push('=');
+        for c in value.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                c => out.push(c),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn parse_file(file: &str) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    for line in file.lines() {
+        let (key, escaped) = match line.split_once('=') {
+            Some(it) => it,
+            None => continue,
+        };
+        let mut value = String::with_capacity(escaped.len());
+        let mut chars = escaped.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some(other) => value.push(other),
+                    None => {}
+                }
+            } else {
+                value.push(c);
+            }
+        }
+        out.insert(key.to_string(), value);
+    }
+    out
+}
+
+/// Get the overrides from the URL query or the command line.
+fn overrides() -> BTreeMap<String, String> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let query = wasm::query();
+        query
+            .trim_start_matches('?')
+            .split('&')
+            .filter_map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                if key.is_empty() {
+                    None
+                } else {
+                    Some((percent_decode(key), percent_decode(value)))
+                }
+            })
+            .collect()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        // `args` panics on arguments that aren't unicode, so skip those instead
+        std::env::args_os()
+            .skip(1)
+            .filter_map(|arg| {
+                let (key, value) = arg.to_str()?.strip_prefix("--")?.split_once('=')?;
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect()
+    }
+}
+
+/// Decode `%XX` escapes and `+` in a URL query.
+#[cfg(target_arch = "wasm32")]
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = [iter.next(), iter.next()];
+                let decoded = match hex {
+                    [Some(hi), Some(lo)] => std::str::from_utf8(&[hi, lo])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                    _ => None,
+                };
+                match decoded {
+                    Some(it) => bytes.push(it),
+                    // Not actually an escape, so leave it as it was
+                    None => {
+                        bytes.push(b'%');
+                        bytes.extend(hex.iter().flatten());
+                    }
+                }
+            }
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use sapp_jsutils::JsObject;
+
+    use crate::objecttools::ObjectTools;
+
+    extern "C" {
+        fn settings_query() -> JsObject;
+    }
+
+    /// Get `window.location.search`.
+    pub fn query() -> String {
+        unsafe { settings_query() }.to_string_direct()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_round_trip() {
+        let mut settings = BTreeMap::new();
+        for (key, value) in [
+            ("plain", "hello"),
+            ("empty", ""),
+            ("equals", "a=b=c"),
+            ("newlines", "one\ntwo\r\nthree"),
+            ("backslashes", "C:\\games\\n\\"),
+        ]
+        .iter()
+        {
+            settings.insert(key.to_string(), value.to_string());
+        }
+        let file = write_file(&settings);
+        assert_eq!(file.lines().count(), settings.len());
+        assert_eq!(parse_file(&file), settings);
+    }
+
+    #[test]
+    fn parse_skips_junk() {
+        let parsed = parse_file("volume=0.5\nnot a setting\r\nlanguage=en\r\n");
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed["volume"], "0.5");
+        assert_eq!(parsed["language"], "en");
+    }
+
+    #[test]
+    fn unknown_keys_survive() {
+        const VOLUME: Key<f32> = Key::new("volume", 0.8);
+        let location = Location::session("settings_unknown_keys_survive");
+        storage::save_to("from_the_future=yes\nvolume=0.25\n", &location).unwrap();
+
+        let mut settings = Settings::load_from(location.clone()).unwrap();
+        assert_eq!(settings.get(&VOLUME), 0.25);
+        settings.set(&VOLUME, 0.5);
+        settings.flush().unwrap();
+
+        let saved = parse_file(&String::from_utf8(storage::load_from(&location).unwrap()).unwrap());
+        assert_eq!(saved["volume"], "0.5");
+        assert_eq!(saved["from_the_future"], "yes");
+    }
+}
@@ -1,14 +1,17 @@
 //! The functions the crate uses to gzip and base64-encode data,
 //! in case they are helpful.
 
-use anyhow::Context;
-use base64::URL_SAFE;
+use anyhow::{bail, Context};
+use base64::{STANDARD, URL_SAFE};
 use flate2::{
-    read::{GzDecoder, GzEncoder},
-    Compression,
+    read::{DeflateDecoder, GzDecoder, GzEncoder},
+    Compress, Compression, FlushCompress, Status,
 };
 
-use std::io::{Cursor, Read};
+use std::{
+    fmt,
+    io::{Cursor, Read},
+};
 
 /// Gzip some binary data.
 ///
@@ -42,3 +45,318 @@ pub fn unzip<T: AsRef<[u8]>>(zipped: T) -> anyhow::Result<Vec<u8>> {
 pub fn unzip64<T: AsRef<str>>(encoded: T) -> anyhow::Result<Vec<u8>> {
     unzip(base64::decode_config(encoded.as_ref(), URL_SAFE).context("When decoding base64")?)
 }
+
+/// Figure out what format some data is in, and turn it back into the original bytes.
+///
+/// This is for reading data that might not have been written by this crate, like saves from old builds
+/// of your game that just stored plain JSON. It tries, in order:
+///
+/// - [`Format::Zip`], if it starts with the gzip magic number.
+/// - [`Format::Zip64`], if it's base64 (`URL_SAFE` or standard) of something that starts with the gzip magic number.
+/// - [`Format::Raw`] otherwise, returning the data as-is.
+///
+/// If it looks like gzip (or base64 of gzip) but doesn't unzip, that's an error.
+/// So a zipped save that got cut off is an error instead of garbage.
+pub fn unzip_any<T: AsRef<[u8]>>(data: T) -> anyhow::Result<(Vec<u8>, Format)> {
+    let data = data.as_ref();
+    if is_gzip(data) {
+        return Ok((unzip(data)?, Format::Zip));
+    }
+
+    let trimmed = std::str::from_utf8(data).map(str::trim);
+    if let Ok(trimmed) = trimmed {
+        let decoded = base64::decode_config(trimmed, URL_SAFE)
+            .or_else(|_| base64::decode_config(trimmed, STANDARD));
+        if let Ok(decoded) = decoded {
+            if is_gzip(&decoded) {
+                return Ok((unzip(decoded)?, Format::Zip64));
+            }
+        } else if trimmed.starts_with(GZIP_BASE64) {
+            bail!("This looks like base64'd gzip, but it isn't valid base64");
+        }
+    }
+
+    Ok((data.to_vec(), Format::Raw))
+}
+
+fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+/// What the gzip magic number and deflate method look like in base64.
+const GZIP_BASE64: &str = "H4sI";
+
+/// The formats [`unzip_any`] can find data in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// Gzipped, like [`zip`] makes.
+    Zip,
+    /// Gzipped and base64-encoded, like [`zip64`] makes.
+    Zip64,
+    /// Not compressed at all. (Probably something like plain JSON.)
+    Raw,
+}
+
+impl Format {
+    /// Check if this is the format [`save_to`] uses on this platform:
+    /// [`Format::Zip`] on desktop, and [`Format::Zip64`] on the web.
+    ///
+    /// If it isn't, you might want to save the data again to bring it up to date.
+    ///
+    /// [`save_to`]: super::save_to
+    pub fn is_current(self) -> bool {
+        #[cfg(target_arch = "wasm32")]
+        {
+            self == Format::Zip64
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self == Format::Zip
+        }
+    }
+}
+
+/// Zlib-compress some data with a preset dictionary.
+///
+/// This is great for lots of small payloads that all look about the same, like
+/// level codes or other JSON blobs: gzip's header and cold start eat most of the space there,
+/// but with a dictionary full of typical content the compressor can refer back to it from the very first byte.
+/// (The last 32KiB of the dictionary is all that gets used, so keep it short and put the most common stuff at the end.)
+///
+/// The output is a normal zlib stream with the `FDICT` flag set, so the [`dict_id`] of the dictionary is recorded in it.
+/// [`unzip_with_dict`] uses that to refuse to decode with the wrong dictionary.
+///
+/// This uses Best-level compression, like [`zip`].
+pub fn zip_with_dict<T: AsRef<[u8]>>(data: T, dict: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let data = data.as_ref();
+
+    // miniz doesn't let you set a dictionary directly, so we prime it instead:
+    // feed it the dictionary, sync-flush so the output is byte-aligned, and throw that output away.
+    // Everything after that point can back-reference into the dictionary.
+    let mut deflater = Compress::new(Compression::best(), false);
+    deflate_into(&mut deflater, dict, FlushCompress::Sync)?;
+    let body = deflate_into(&mut deflater, data, FlushCompress::Finish)?;
+
+    let mut out = Vec::with_capacity(body.len() + 10);
+    out.extend_from_slice(&zlib_dict_header());
+    out.extend_from_slice(&dict_id(dict).to_be_bytes());
+    out.extend_from_slice(&body);
+    out.extend_from_slice(&adler::adler32_slice(data).to_be_bytes());
+    Ok(out)
+}
+
+/// Zlib-compress some data with a preset dictionary, then return it as a base64 string.
+///
+/// This uses `URL_SAFE` base64.
+pub fn zip64_with_dict<T: AsRef<[u8]>>(data: T, dict: &[u8]) -> anyhow::Result<String> {
+    Ok(base64::encode_config(
+        zip_with_dict(data.as_ref(), dict)?,
+        URL_SAFE,
+    ))
+}
+
+/// Unzip something made with [`zip_with_dict`] back into the original bytes.
+///
+/// The dictionary must be the same one it was compressed with.
+/// If it isn't, this returns a [`DictionaryMismatch`] error (wrapped in `anyhow`, so `downcast_ref` it if you care).
+pub fn unzip_with_dict<T: AsRef<[u8]>>(zipped: T, dict: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let zipped = zipped.as_ref();
+    if zipped.len() < 10 {
+        bail!("Too short to be zlib data with a dictionary");
+    }
+    let (header, rest) = zipped.split_at(2);
+    if header[0] & 0x0f != 8 || u16::from_be_bytes([header[0], header[1]]) % 31 != 0 {
+        bail!("Bad zlib header");
+    }
+    if header[1] & FDICT == 0 {
+        bail!("This zlib data wasn't compressed with a dictionary");
+    }
+
+    let (found, rest) = rest.split_at(4);
+    let found = u32::from_be_bytes([found[0], found[1], found[2], found[3]]);
+    let expected = dict_id(dict);
+    if found != expected {
+        bail!(DictionaryMismatch { expected, found });
+    }
+    let (body, checksum) = rest.split_at(rest.len() - 4);
+    let checksum = u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+
+    // And to decompress we do the same trick backwards: stick the dictionary in front of the
+    // compressed data as uncompressed blocks, and then snip it off the output.
+    let mut primed = Vec::with_capacity(dict.len() + dict.len() / 0xffff * 5 + body.len() + 5);
+    for chunk in dict.chunks(0xffff) {
+        let len = chunk.len() as u16;
+        // Non-final stored block; the 3 header bits get padded out to a full byte
+        primed.push(0);
+        primed.extend_from_slice(&len.to_le_bytes());
+        primed.extend_from_slice(&(!len).to_le_bytes());
+        primed.extend_from_slice(chunk);
+    }
+    primed.extend_from_slice(body);
+
+    let mut inflater = DeflateDecoder::new(Cursor::new(primed));
+    let mut out = Vec::new();
+    inflater.read_to_end(&mut out).context("When un-zlibbing")?;
+    let out = out.split_off(dict.len());
+
+    if adler::adler32_slice(&out) != checksum {
+        bail!("Checksum mismatch when un-zlibbing");
+    }
+    Ok(out)
+}
+
+/// Decode a base64 string, then unzip it with a dictionary back into the original bytes.
+///
+/// This expects `URL_SAFE` base64.
+pub fn unzip64_with_dict<T: AsRef<str>>(encoded: T, dict: &[u8]) -> anyhow::Result<Vec<u8>> {
+    unzip_with_dict(
+        base64::decode_config(encoded.as_ref(), URL_SAFE).context("When decoding base64")?,
+        dict,
+    )
+}
+
+/// Get the ID of a dictionary, as stored by [`zip_with_dict`].
+///
+/// This is its Adler-32 checksum, same as zlib uses.
+pub fn dict_id(dict: &[u8]) -> u32 {
+    adler::adler32_slice(dict)
+}
+
+/// Error due to trying to decode data with a different dictionary than it was compressed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DictionaryMismatch {
+    /// The ID of the dictionary we were given
+    pub expected: u32,
+    /// The ID stored in the data
+    pub found: u32,
+}
+
+impl fmt::Display for DictionaryMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Expected dictionary ID {:#010x}, but the data was compressed with {:#010x}",
+            self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for DictionaryMismatch {}
+
+/// The `FDICT` bit in the zlib `FLG` byte.
+const FDICT: u8 = 0b0010_0000;
+
+/// Zlib header for deflate with a 32KiB window, best compression, and a preset dictionary.
+fn zlib_dict_header() -> [u8; 2] {
+    let cmf = 0x78;
+    let flg = 0b1100_0000 | FDICT;
+    let check = 31 - (u16::from_be_bytes([cmf, flg]) % 31);
+    [cmf, flg | check as u8]
+}
+
+/// Run all of `input` through the deflater, flushing at the end, and return what comes out.
+fn deflate_into(
+    deflater: &mut Compress,
+    input: &[u8],
+    flush: FlushCompress,
+) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 64);
+    let start = deflater.total_in();
+    loop {
+        if out.len() == out.capacity() {
+            out.reserve(out.capacity());
+        }
+        let consumed = (deflater.total_in() - start) as usize;
+        let status = deflater
+            .compress_vec(&input[consumed..], &mut out, flush)
+            .context("When zlibbing")?;
+        let consumed = (deflater.total_in() - start) as usize;
+        // If there's room left over after a flush, the flush is done
+        let done = match status {
+            Status::StreamEnd => true,
+            _ => {
+                !matches!(flush, FlushCompress::Finish)
+                    && consumed == input.len()
+                    && out.len() < out.capacity()
+            }
+        };
+        if done {
+            return Ok(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_formats() {
+        let data = br#"{"level":3}"#;
+        assert_eq!(
+            unzip_any(zip(data).unwrap()).unwrap(),
+            (data.to_vec(), Format::Zip)
+        );
+        assert_eq!(
+            unzip_any(zip64(data).unwrap()).unwrap(),
+            (data.to_vec(), Format::Zip64)
+        );
+        assert_eq!(unzip_any(data).unwrap(), (data.to_vec(), Format::Raw));
+    }
+
+    #[test]
+    fn sniff_truncated() {
+        let data = br#"{"level":3,"name":"a level with a long enough name"}"#;
+        let zipped = zip(data).unwrap();
+        let zipped64 = zip64(data).unwrap();
+        for end in [zipped.len() / 2, zipped.len() - 1].iter() {
+            assert!(unzip_any(&zipped[..*end]).is_err());
+        }
+        for end in [zipped64.len() / 2, zipped64.len() - 1, zipped64.len() - 4].iter() {
+            assert!(unzip_any(&zipped64[..*end]).is_err());
+        }
+    }
+
+    const DICT: &[u8] = br#"{"name":"","width":16,"height":16,"tiles":[0,0,0,0,0,0,0,0]}"#;
+
+    #[test]
+    fn dict_round_trip() {
+        let data = br#"{"name":"level 1","width":16,"height":16,"tiles":[0,0,1,1,0,0,2,0]}"#;
+        let zipped = zip_with_dict(data, DICT).unwrap();
+        assert_eq!(unzip_with_dict(&zipped, DICT).unwrap(), data);
+        // It should actually use the dictionary
+        assert!(zipped.len() < zip(data).unwrap().len());
+
+        let zipped = zip64_with_dict(data, DICT).unwrap();
+        assert_eq!(unzip64_with_dict(&zipped, DICT).unwrap(), data);
+    }
+
+    #[test]
+    fn dict_round_trip_edges() {
+        for data in [&b""[..], &[7; 100_000][..]].iter() {
+            for dict in [&b"x"[..], &[3; 70_000][..]].iter() {
+                let zipped = zip_with_dict(data, dict).unwrap();
+                assert_eq!(&unzip_with_dict(&zipped, dict).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn dict_mismatch() {
+        let zipped = zip_with_dict(b"hello hello", DICT).unwrap();
+        let err = unzip_with_dict(&zipped, b"some other dictionary").unwrap_err();
+        let mismatch = err.downcast_ref::<DictionaryMismatch>().unwrap();
+        assert_eq!(mismatch.found, dict_id(DICT));
+        assert_eq!(mismatch.expected, dict_id(b"some other dictionary"));
+    }
+
+    #[test]
+    fn dict_corrupt() {
+        let mut zipped = zip_with_dict(b"hello hello hello", DICT).unwrap();
+        let last = zipped.len() - 1;
+        zipped[last] ^= 1;
+        assert!(unzip_with_dict(&zipped, DICT).is_err());
+        assert!(unzip_with_dict(&zipped[..8], DICT).is_err());
+        assert!(unzip_with_dict(zip(b"hello").unwrap(), DICT).is_err());
+    }
+}
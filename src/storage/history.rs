@@ -0,0 +1,327 @@
+//! Keeping track of where saves came from, so they can be moved between the web and desktop without clobbering anything.
+//!
+//! When players export a save from one build of your game and import it into another, they can end up with
+//! two copies that have both moved on since. These functions save some history along with your data:
+//! each install of your game gets a random origin ID, and every save bumps a generation counter for
+//! the install that made it. Comparing two histories tells you if one save is newer, older, or if they've branched off
+//! from each other, so you can ask the player what to do.
+//!
+//! The origin ID is stored next to the save, at the same location but with `.origin` stuck on the end of the `bin_name`,
+//! so it can't clash with any of your profiles.
+
+use super::{
+    flate::{unzip, zip},
+    Location, Scope,
+};
+
+use anyhow::{bail, Context};
+
+use std::{cmp::Ordering, collections::BTreeMap};
+
+/// The history of a save: how many times each install has saved it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SaveMeta {
+    generations: BTreeMap<u64, u64>,
+}
+
+impl SaveMeta {
+    /// How many times this save has been saved, everywhere.
+    pub fn generation(&self) -> u64 {
+        self.generations.values().sum()
+    }
+
+    /// How many times the install with the given origin ID has saved this save.
+    pub fn generation_from(&self, origin: u64) -> u64 {
+        self.generations.get(&origin).copied().unwrap_or(0)
+    }
+
+    /// The origin IDs of every install that has saved this save.
+    pub fn origins(&self) -> impl Iterator<Item = u64> + '_ {
+        self.generations.keys().copied()
+    }
+
+    /// Combine two histories, so the result is at least as new as both.
+    pub fn merge(&self, other: &SaveMeta) -> SaveMeta {
+        let mut out = self.clone();
+        for (&origin, &generation) in other.generations.iter() {
+            let ours = out.generations.entry(origin).or_insert(0);
+            *ours = (*ours).max(generation);
+        }
+        out
+    }
+
+    /// Figure out how this save relates to the `local` one.
+    pub fn compare(&self, local: &SaveMeta) -> Comparison {
+        let mut ahead = false;
+        let mut behind = false;
+        for origin in self.origins().chain(local.origins()) {
+            match self
+                .generation_from(origin)
+                .cmp(&local.generation_from(origin))
+            {
+                Ordering::Greater => ahead = true,
+                Ordering::Less => behind = true,
+                Ordering::Equal => {}
+            }
+        }
+        match (ahead, behind) {
+            (false, false) => Comparison::Same,
+            (true, false) => Comparison::Newer,
+            (false, true) => Comparison::Older,
+            (true, true) => Comparison::Conflict,
+        }
+    }
+}
+
+/// How one save relates to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    /// They're the same save.
+    Same,
+    /// This save has everything the other one has, and more. It's safe to replace the other one with it.
+    Newer,
+    /// The other save has everything this one has, and more. This one is out of date.
+    Older,
+    /// They both have changes the other doesn't, so one of them has to lose. Better ask the player which.
+    Conflict,
+}
+
+/// Save some data to the given location, bumping its generation.
+///
+/// If this returns `Err` it's *not* guaranteed that we made no edits
+/// to the filesystem or localstorage.
+pub fn save_to<T: AsRef<[u8]>>(data: T, location: &Location) -> anyhow::Result<SaveMeta> {
+    let meta = local_meta(location)?;
+    save_bumped(data.as_ref(), &meta, location)
+}
+
+/// Load some data and its history from the given location.
+///
+/// If the data was saved without any history (say, with plain [`save_to`](super::save_to)),
+/// you get an empty history.
+pub fn load_from(location: &Location) -> anyhow::Result<(Vec<u8>, SaveMeta)> {
+    decode(&super::load_from(location)?)
+}
+
+/// Get the save at the given location, history and all, ready to be handed to the player.
+///
+/// This is gzipped; pass it to [`files::download`](crate::files::download),
+/// or base64 it if you want it as text.
+pub fn export_from(location: &Location) -> anyhow::Result<Vec<u8>> {
+    let (data, meta) = load_from(location)?;
+    zip(encode(&data, &meta))
+}
+
+/// Read a save made by [`export_from`], without doing anything with it.
+pub fn read_export<T: AsRef<[u8]>>(exported: T) -> anyhow::Result<(Vec<u8>, SaveMeta)> {
+    decode(&unzip(exported).context("When unzipping the exported save")?)
+}
+
+/// Figure out how an exported save relates to the one at the given location.
+///
+/// If there's nothing saved there yet, it's [`Comparison::Newer`] (or [`Comparison::Same`] if the export has no history).
+pub fn compare_import<T: AsRef<[u8]>>(
+    exported: T,
+    location: &Location,
+) -> anyhow::Result<Comparison> {
+    let (_, imported) = read_export(exported)?;
+    Ok(imported.compare(&local_meta(location)?))
+}
+
+/// Replace the save at the given location with an exported one.
+///
+/// The histories are merged, so afterwards the local save counts as newer than both.
+pub fn import_to<T: AsRef<[u8]>>(exported: T, location: &Location) -> anyhow::Result<SaveMeta> {
+    let (data, imported) = read_export(exported)?;
+    let meta = local_meta(location)?.merge(&imported);
+    save_bumped(&data, &meta, location)
+}
+
+/// Keep the save at the given location, but take on an exported save's history,
+/// so importing that same save again won't show up as a conflict.
+pub fn dismiss_import<T: AsRef<[u8]>>(
+    exported: T,
+    location: &Location,
+) -> anyhow::Result<SaveMeta> {
+    let (_, imported) = read_export(exported)?;
+    let (data, local) = load_from(location)?;
+    save_bumped(&data, &local.merge(&imported), location)
+}
+
+/// Get this install's origin ID, making one if there isn't one yet.
+pub fn origin_id(location: &Location) -> anyhow::Result<u64> {
+    let origin_location = Location {
+        bin_name: format!("{}.origin", &location.bin_name),
+        profile: String::from("origin"),
+        scope: Scope::Persistent,
+        ..location.clone()
+    };
+    if super::exists(&origin_location)? {
+        let bytes = super::load_from(&origin_location)?;
+        if bytes.len() == 8 {
+            let mut id = [0; 8];
+            id.copy_from_slice(&bytes);
+            return Ok(u64::from_le_bytes(id));
+        }
+    }
+    let id = random_u64();
+    super::save_to(id.to_le_bytes(), &origin_location)?;
+    Ok(id)
+}
+
+fn local_meta(location: &Location) -> anyhow::Result<SaveMeta> {
+    if super::exists(location)? {
+        Ok(load_from(location)?.1)
+    } else {
+        Ok(SaveMeta::default())
+    }
+}
+
+fn save_bumped(data: &[u8], meta: &SaveMeta, location: &Location) -> anyhow::Result<SaveMeta> {
+    let mut meta = meta.clone();
+    *meta.generations.entry(origin_id(location)?).or_insert(0) += 1;
+    super::save_to(encode(data, &meta), location)?;
+    Ok(meta)
+}
+
+/// Put at the front of saved data so we can tell it has history.
+const MAGIC: &[u8; 4] = b"QWH1";
+
+/// The magic, the number of origins, each origin and generation, and then the data.
+fn encode(data: &[u8], meta: &SaveMeta) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 8 + meta.generations.len() * 16);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(meta.generations.len() as u32).to_le_bytes());
+    for (origin, generation) in meta.generations.iter() {
+        out.extend_from_slice(&origin.to_le_bytes());
+        out.extend_from_slice(&generation.to_le_bytes());
+    }
+    out.extend_from_slice(data);
+    out
+}
+
+fn decode(bytes: &[u8]) -> anyhow::Result<(Vec<u8>, SaveMeta)> {
+    let rest = match bytes.strip_prefix(MAGIC) {
+        Some(it) => it,
+        // No history
+        None => return Ok((bytes.to_vec(), SaveMeta::default())),
+    };
+    if rest.len() < 4 {
+        bail!("Save history was cut off");
+    }
+    let (count, mut rest) = rest.split_at(4);
+    let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+
+    let mut meta = SaveMeta::default();
+    for _ in 0..count {
+        if rest.len() < 16 {
+            bail!("Save history was cut off");
+        }
+        let (pair, next) = rest.split_at(16);
+        let mut origin = [0; 8];
+        let mut generation = [0; 8];
+        origin.copy_from_slice(&pair[..8]);
+        generation.copy_from_slice(&pair[8..]);
+        meta.generations
+            .insert(u64::from_le_bytes(origin), u64::from_le_bytes(generation));
+        rest = next;
+    }
+    Ok((rest.to_vec(), meta))
+}
+
+/// Doesn't need to be cryptographically secure, just different on every install.
+pub(super) fn random_u64() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let hi = unsafe { wasm::random_u32() } as u64;
+        let lo = unsafe { wasm::random_u32() } as u64;
+        (hi << 32) | lo
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::{
+            collections::hash_map::RandomState,
+            hash::{BuildHasher, Hash, Hasher},
+            time::SystemTime,
+        };
+
+        // RandomState is seeded randomly by the OS
+        let mut hasher = RandomState::new().build_hasher();
+        SystemTime::now().hash(&mut hasher);
+        std::process::id().hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    extern "C" {
+        /// Uses `crypto.getRandomValues` if it can, or `Math.random` if it can't.
+        pub fn random_u32() -> u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(generations: &[(u64, u64)]) -> SaveMeta {
+        SaveMeta {
+            generations: generations.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn compare() {
+        let local = meta(&[(1, 3), (2, 1)]);
+        assert_eq!(local.compare(&local), Comparison::Same);
+        assert_eq!(meta(&[]).compare(&meta(&[])), Comparison::Same);
+
+        assert_eq!(meta(&[(1, 4), (2, 1)]).compare(&local), Comparison::Newer);
+        assert_eq!(
+            meta(&[(1, 3), (2, 1), (3, 1)]).compare(&local),
+            Comparison::Newer
+        );
+        assert_eq!(local.compare(&meta(&[])), Comparison::Newer);
+
+        assert_eq!(meta(&[(1, 2), (2, 1)]).compare(&local), Comparison::Older);
+        assert_eq!(meta(&[(1, 3)]).compare(&local), Comparison::Older);
+
+        assert_eq!(
+            meta(&[(1, 4), (2, 0)]).compare(&local),
+            Comparison::Conflict
+        );
+        assert_eq!(
+            meta(&[(1, 3), (3, 1)]).compare(&local),
+            Comparison::Conflict
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let ours = meta(&[(1, 3), (2, 1)]);
+        let theirs = meta(&[(1, 4), (3, 2)]);
+        let merged = ours.merge(&theirs);
+        assert_eq!(merged, meta(&[(1, 4), (2, 1), (3, 2)]));
+        assert_eq!(ours.compare(&merged), Comparison::Older);
+        assert_eq!(theirs.compare(&merged), Comparison::Older);
+        assert_eq!(merged.generation(), 7);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let meta = meta(&[(u64::MAX, 1), (7, 12)]);
+        let encoded = encode(b"some data", &meta);
+        assert_eq!(decode(&encoded).unwrap(), (b"some data".to_vec(), meta));
+
+        // Data without history comes back with none
+        assert_eq!(
+            decode(b"some data").unwrap(),
+            (b"some data".to_vec(), SaveMeta::default())
+        );
+
+        for end in MAGIC.len()..MAGIC.len() + 4 + 32 {
+            assert!(decode(&encoded[..end]).is_err());
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! Append-only storage, for things that grow a little bit every frame like input replays and event logs.
+//!
+//! Re-saving a whole blob with [`save_to`] every time you add something gets slow fast,
+//! so a [`Log`] only ever writes the new bit.
+//!
+//! - On desktop, records get appended to `/path/to/datadir/{bin_name}/v{version}/{profile}.log`.
+//! - On localstorage, records are stored in chunks of about 16KiB under the keys
+//!   `"{bin_name}/v{version}/{profile}/log/{n}"`, and the number of chunks is stored under
+//!   `"{bin_name}/v{version}/{profile}/log"`. Only the last chunk gets rewritten when you append.
+//!
+//! Each record is prefixed with its length (as a little-endian `u32`), so if the game dies halfway through
+//! writing one, the torn record is detected and dropped the next time the log is opened.
+//!
+//! If the [`Location`]'s scope is [`Scope::Session`], the log goes in the same places its saves would.
+//!
+//! Unlike [`save_to`], log data is not gzipped (there's not much point zipping one record at a time).
+//!
+//! [`save_to`]: super::save_to
+//! [`Scope::Session`]: super::Scope::Session
+
+use super::Location;
+#[cfg(target_arch = "wasm32")]
+use super::Scope;
+
+/// A log of records stored at some [`Location`].
+///
+/// This keeps track of where the end of the log is, so keep it around
+/// instead of opening it again every time you want to append something.
+#[derive(Debug)]
+pub struct Log {
+    /// How many records are in the log
+    len: usize,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    path: std::path::PathBuf,
+
+    /// Whether the chunks are in localstorage or sessionstorage
+    #[cfg(target_arch = "wasm32")]
+    scope: Scope,
+    /// Base key for the chunks
+    #[cfg(target_arch = "wasm32")]
+    key: String,
+    /// How many chunks there are
+    #[cfg(target_arch = "wasm32")]
+    chunks: usize,
+    /// The raw bytes of the last chunk, so we don't have to load and decode it every append
+    #[cfg(target_arch = "wasm32")]
+    last_chunk: Vec<u8>,
+}
+
+impl Log {
+    /// Open the log at the given location, creating it if it doesn't exist.
+    ///
+    /// If the log ends in a torn record, it's dropped here.
+    pub fn open(location: &Location) -> anyhow::Result<Self> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let key = format!("{}/log", location.key());
+            let chunks = wasm::chunk_count(location.scope, &key)?;
+            let mut len = 0;
+            let mut last_chunk = Vec::new();
+            for idx in 0..chunks {
+                let chunk = wasm::load_chunk(location.scope, &key, idx)?;
+                let (records, valid) = parse_records(&chunk);
+                len += records.len();
+                last_chunk = chunk;
+                last_chunk.truncate(valid);
+            }
+            Ok(Self {
+                len,
+                scope: location.scope,
+                key,
+                chunks,
+                last_chunk,
+            })
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use anyhow::{anyhow, Context};
+            use std::{fs::OpenOptions, io::ErrorKind};
+
+            let path = location.path()?.with_extension("log");
+            std::fs::create_dir_all(
+                path.parent()
+                    .ok_or_else(|| anyhow!("Couldn't get parent of {:?}", &path))?,
+            )?;
+
+            let len = match std::fs::read(&path) {
+                Ok(bytes) => {
+                    let (records, valid) = parse_records(&bytes);
+                    if valid < bytes.len() {
+                        // Chop off the torn bit so appends go after the last good record
+                        OpenOptions::new()
+                            .write(true)
+                            .open(&path)
+                            .and_then(|file| file.set_len(valid as u64))
+                            .with_context(|| {
+                                anyhow!("When dropping a torn record from {:?}", &path)
+                            })?;
+                    }
+                    records.len()
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => 0,
+                Err(e) => {
+                    return Err(e).with_context(|| anyhow!("When reading the log at {:?}", &path))
+                }
+            };
+            Ok(Self { len, path })
+        }
+    }
+
+    /// Add a record to the end of the log.
+    ///
+    /// If this returns `Err` it's *not* guaranteed that we made no edits
+    /// to the filesystem or localstorage. (But if it did, the next [`Log::open`]
+    /// will clean up the mess.)
+    pub fn append<T: AsRef<[u8]>>(&mut self, record: T) -> anyhow::Result<()> {
+        let record = record.as_ref();
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            if self.chunks == 0 || self.last_chunk.len() >= wasm::CHUNK_SIZE {
+                self.last_chunk.clear();
+                push_record(&mut self.last_chunk, record);
+                // Write the chunk before the count so the count never points at something missing
+                wasm::save_chunk(self.scope, &self.key, self.chunks, &self.last_chunk)?;
+                wasm::set_chunk_count(self.scope, &self.key, self.chunks + 1)?;
+                self.chunks += 1;
+            } else {
+                push_record(&mut self.last_chunk, record);
+                wasm::save_chunk(self.scope, &self.key, self.chunks - 1, &self.last_chunk)?;
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use anyhow::{anyhow, Context};
+            use std::{fs::OpenOptions, io::Write};
+
+            let mut buf = Vec::with_capacity(record.len() + 4);
+            push_record(&mut buf, record);
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .and_then(|mut file| file.write_all(&buf))
+                .with_context(|| anyhow!("When appending to the log at {:?}", &self.path))?;
+        }
+
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Read all the records in the log, oldest first.
+    pub fn records(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let mut out = Vec::with_capacity(self.len);
+            for idx in 0..self.chunks {
+                let chunk = if idx + 1 == self.chunks {
+                    self.last_chunk.clone()
+                } else {
+                    wasm::load_chunk(self.scope, &self.key, idx)?
+                };
+                out.extend(parse_records(&chunk).0);
+            }
+            Ok(out)
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use anyhow::{anyhow, Context};
+            use std::io::ErrorKind;
+
+            match std::fs::read(&self.path) {
+                Ok(bytes) => Ok(parse_records(&bytes).0),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+                Err(e) => {
+                    Err(e).with_context(|| anyhow!("When reading the log at {:?}", &self.path))
+                }
+            }
+        }
+    }
+
+    /// How many records are in the log.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if there are no records in the log.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Throw away everything but the first `len` records.
+    ///
+    /// If there are fewer than `len` records this does nothing.
+    pub fn truncate(&mut self, len: usize) -> anyhow::Result<()> {
+        if len >= self.len {
+            return Ok(());
+        }
+        let mut records = self.records()?;
+        records.truncate(len);
+        self.rewrite(&records)
+    }
+
+    /// Throw away everything but the last `keep` records, and rewrite the log
+    /// without any wasted space.
+    ///
+    /// This is handy for keeping telemetry from growing forever.
+    pub fn compact(&mut self, keep: usize) -> anyhow::Result<()> {
+        let mut records = self.records()?;
+        let start = records.len().saturating_sub(keep);
+        records.drain(..start);
+        self.rewrite(&records)
+    }
+
+    /// Throw away every record in the log.
+    pub fn clear(&mut self) -> anyhow::Result<()> {
+        self.rewrite(&[])
+    }
+
+    /// Replace everything in the log with the given records.
+    ///
+    /// On desktop this writes a new file and then moves it over the old one,
+    /// so the log is either all old or all new.
+    /// On the web it's done chunk by chunk, so if it fails partway through it might
+    /// be a mix of both.
+    fn rewrite(&mut self, records: &[Vec<u8>]) -> anyhow::Result<()> {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let mut chunks = Vec::new();
+            let mut chunk = Vec::new();
+            for record in records {
+                if chunk.len() >= wasm::CHUNK_SIZE {
+                    chunks.push(std::mem::take(&mut chunk));
+                }
+                push_record(&mut chunk, record);
+            }
+            if !chunk.is_empty() {
+                chunks.push(chunk);
+            }
+
+            for (idx, chunk) in chunks.iter().enumerate() {
+                wasm::save_chunk(self.scope, &self.key, idx, chunk)?;
+            }
+            wasm::set_chunk_count(self.scope, &self.key, chunks.len())?;
+            for idx in chunks.len()..self.chunks {
+                wasm::remove_chunk(self.scope, &self.key, idx)?;
+            }
+
+            self.chunks = chunks.len();
+            self.last_chunk = chunks.pop().unwrap_or_default();
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use anyhow::{anyhow, Context};
+
+            let mut buf = Vec::new();
+            for record in records {
+                push_record(&mut buf, record);
+            }
+            let tmp = self.path.with_extension("log.tmp");
+            std::fs::write(&tmp, &buf)
+                .and_then(|()| std::fs::rename(&tmp, &self.path))
+                .with_context(|| anyhow!("When rewriting the log at {:?}", &self.path))?;
+        }
+
+        self.len = records.len();
+        Ok(())
+    }
+}
+
+/// Add the length prefix and the record to the buffer.
+pub(super) fn push_record(buf: &mut Vec<u8>, record: &[u8]) {
+    buf.extend_from_slice(&(record.len() as u32).to_le_bytes());
+    buf.extend_from_slice(record);
+}
+
+/// Split a buffer up into records.
+///
+/// Also returns how many bytes of the buffer were good records;
+/// anything after that is a torn record.
+pub(super) fn parse_records(mut buf: &[u8]) -> (Vec<Vec<u8>>, usize) {
+    let mut records = Vec::new();
+    let mut valid = 0;
+    while buf.len() >= 4 {
+        let len = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        if buf.len() - 4 < len {
+            break;
+        }
+        records.push(buf[4..4 + len].to_vec());
+        buf = &buf[4 + len..];
+        valid += 4 + len;
+    }
+    (records, valid)
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use crate::storage::{
+        wasm::{get_item, remove_item, set_item},
+        Scope,
+    };
+
+    use anyhow::Context;
+    use base64::URL_SAFE;
+
+    /// Start a new chunk once the last one is at least this big.
+    pub const CHUNK_SIZE: usize = 16 * 1024;
+
+    pub fn chunk_count(scope: Scope, key: &str) -> anyhow::Result<usize> {
+        match get_item(scope, key)? {
+            Some(count) => count.parse().context("When reading the log's chunk count"),
+            None => Ok(0),
+        }
+    }
+    pub fn set_chunk_count(scope: Scope, key: &str, count: usize) -> anyhow::Result<()> {
+        set_item(scope, key, &count.to_string())
+    }
+
+    /// A missing chunk is treated as empty.
+    pub fn load_chunk(scope: Scope, key: &str, idx: usize) -> anyhow::Result<Vec<u8>> {
+        match get_item(scope, &format!("{}/{}", key, idx))? {
+            Some(chunk) => base64::decode_config(&chunk, URL_SAFE).context("When decoding base64"),
+            None => Ok(Vec::new()),
+        }
+    }
+    pub fn save_chunk(scope: Scope, key: &str, idx: usize, chunk: &[u8]) -> anyhow::Result<()> {
+        set_item(
+            scope,
+            &format!("{}/{}", key, idx),
+            &base64::encode_config(chunk, URL_SAFE),
+        )
+    }
+    pub fn remove_chunk(scope: Scope, key: &str, idx: usize) -> anyhow::Result<()> {
+        remove_item(scope, &format!("{}/{}", key, idx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records_buf(records: &[&[u8]]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for record in records {
+            push_record(&mut buf, record);
+        }
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let records: &[&[u8]] = &[b"hello", b"", b"world!"];
+        let buf = records_buf(records);
+        let (parsed, valid) = parse_records(&buf);
+        assert_eq!(parsed, records);
+        assert_eq!(valid, buf.len());
+    }
+
+    #[test]
+    fn torn_tail() {
+        let good = records_buf(&[b"one", b"two"]);
+        let mut buf = good.clone();
+        push_record(&mut buf, b"three");
+
+        // Chop it off anywhere in the last record, including partway through its length
+        for end in good.len()..buf.len() {
+            let (parsed, valid) = parse_records(&buf[..end]);
+            assert_eq!(parsed, [&b"one"[..], b"two"]);
+            assert_eq!(valid, good.len());
+        }
+    }
+
+    #[test]
+    fn torn_tail_on_disk() {
+        use std::io::Write;
+
+        let location = Location::session("log_torn_tail_on_disk");
+        let mut log = Log::open(&location).unwrap();
+        log.clear().unwrap();
+        log.append(b"one").unwrap();
+        log.append(b"two").unwrap();
+
+        // Pretend we died halfway through writing a record
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&log.path)
+            .unwrap()
+            .write_all(&[5, 0, 0, 0, b't', b'h'])
+            .unwrap();
+
+        let mut log = Log::open(&location).unwrap();
+        assert_eq!(log.len(), 2);
+        log.append(b"three").unwrap();
+        assert_eq!(log.records().unwrap(), [&b"one"[..], b"two", b"three"]);
+    }
+}
@@ -1,9 +1,17 @@
 //! Store data across play sessions via persistent storage.
 
 pub mod flate;
+pub mod history;
+pub mod log;
+pub mod signed;
+pub mod transaction;
+pub mod usage;
 
+use self::flate::Format;
 #[cfg(not(target_arch = "wasm32"))]
-use self::flate::{unzip, zip};
+use self::flate::{unzip_any, zip};
+
+use crate::waiter::Waiter;
 
 #[cfg(not(target_arch = "wasm32"))]
 use anyhow::{anyhow, Context};
@@ -12,6 +20,8 @@ use anyhow::{anyhow, Context};
 ///
 /// - On desktop, data is stored to `/path/to/datadir/{bin_name}/v{version}/{profile}.dat`.
 /// - On localstorage, data is stored under the key `"{bin_name}/v{version}/{profile}"`.
+///
+/// (Unless the [`Scope`] is [`Scope::Session`]; see its docs for where that goes.)
 #[derive(Debug, Clone)]
 pub struct Location {
     /// The name of your binary crate, via `env!("CARGO_PKG_NAME")`.
@@ -33,13 +43,38 @@ pub struct Location {
     ///
     /// Is `"default"` by default.
     pub profile: String,
+    /// How long the data should stick around for.
+    ///
+    /// Is [`Scope::Persistent`] by default.
+    pub scope: Scope,
 }
 
 impl Location {
+    /// Make a location for the given profile that keeps its data across play sessions.
+    ///
+    /// Everything else is the [default](Location::default).
+    /// This is handier than writing out the struct, and won't break if `Location` grows more fields.
+    pub fn new(profile: impl Into<String>) -> Self {
+        Self {
+            profile: profile.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Make a location for the given profile whose data is thrown away when the game is closed.
+    ///
+    /// See [`Scope::Session`].
+    pub fn session(profile: impl Into<String>) -> Self {
+        Self {
+            scope: Scope::Session,
+            ..Self::new(profile)
+        }
+    }
+
     /// Get the path to save the data to.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn path(&self) -> anyhow::Result<std::path::PathBuf> {
-        let root = dirs::data_dir().ok_or_else(|| anyhow!("Couldn't find data dir!"))?;
+        let root = self.scope.root()?;
         Ok(root
             .join(&self.bin_name)
             .join(format!("v{}", &self.version))
@@ -60,6 +95,83 @@ impl Default for Location {
             bin_name: env!("CARGO_PKG_NAME").to_string(),
             version: env!("CARGO_PKG_VERSION").to_string(),
             profile: String::from("default"),
+            scope: Scope::Persistent,
+        }
+    }
+}
+
+/// How long some data should stick around for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Scope {
+    /// Keep the data across play sessions.
+    ///
+    /// - On desktop, this is in your data directory.
+    /// - On the web, this is in localstorage.
+    #[default]
+    Persistent,
+    /// Throw the data away when the game is closed.
+    ///
+    /// This is handy for something like an in-progress run that shouldn't survive quitting.
+    ///
+    /// - On desktop, this is in a temporary directory, `/path/to/tempdir/wasmnastics-session-{process id}-{random}/`,
+    ///   which only the player can get into and gets deleted when the process exits normally.
+    ///   (If it crashes, the OS will clean it up eventually. The random bit means a later process can't pick it up.)
+    /// - On the web, this is in [session storage](https://developer.mozilla.org/en-US/docs/Web/API/Window/sessionStorage),
+    ///   which is cleared when the tab is closed. (But not when it's reloaded!)
+    Session,
+}
+
+impl Scope {
+    /// Get the directory all data in this scope goes in.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn root(self) -> anyhow::Result<std::path::PathBuf> {
+        match self {
+            Scope::Persistent => dirs::data_dir().ok_or_else(|| anyhow!("Couldn't find data dir!")),
+            Scope::Session => {
+                use std::{path::PathBuf, sync::Mutex};
+
+                static SESSION_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+                extern "C" fn cleanup() {
+                    // Don't hang on the way out if something else has it
+                    if let Ok(dir) = SESSION_DIR.try_lock() {
+                        if let Some(dir) = dir.as_ref() {
+                            let _ = std::fs::remove_dir_all(dir);
+                        }
+                    }
+                }
+
+                let mut dir = SESSION_DIR.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(dir) = dir.as_ref() {
+                    return Ok(dir.clone());
+                }
+
+                let path = std::env::temp_dir().join(format!(
+                    "wasmnastics-session-{}-{:016x}",
+                    std::process::id(),
+                    history::random_u64()
+                ));
+                // Nothing should be there, but make sure we don't pick up anything left over
+                let _ = std::fs::remove_dir_all(&path);
+                let mut builder = std::fs::DirBuilder::new();
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::DirBuilderExt;
+                    // Only the player can look in here
+                    builder.mode(0o700);
+                }
+                builder
+                    .create(&path)
+                    .with_context(|| anyhow!("When making the session dir at {:?}", &path))?;
+
+                // SAFETY: `cleanup` is a plain `extern "C"` fn that doesn't unwind,
+                // and this only runs once since `SESSION_DIR` is set right after.
+                unsafe {
+                    libc::atexit(cleanup);
+                }
+                *dir = Some(path.clone());
+                Ok(path)
+            }
         }
     }
 }
@@ -69,15 +181,7 @@ impl Default for Location {
 /// If this returns `Err` it's *not* guaranteed that we made no edits
 /// to the filesystem or localstorage.
 pub fn save<T: AsRef<[u8]>>(data: T) -> anyhow::Result<()> {
-    #[cfg(target_arch = "wasm32")]
-    {
-        wasm::save(&Location::default().key(), data.as_ref())
-    }
-
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        save_to(data, &Location::default())
-    }
+    save_to(data, &Location::default())
 }
 
 /// Save some data to the given location.
@@ -85,16 +189,17 @@ pub fn save<T: AsRef<[u8]>>(data: T) -> anyhow::Result<()> {
 /// If this returns `Err` it's *not* guaranteed that we made no edits
 /// to the filesystem or localstorage.
 pub fn save_to<T: AsRef<[u8]>>(data: T, location: &Location) -> anyhow::Result<()> {
+    transaction::recover(&location.bin_name, location.scope)?;
+
     #[cfg(target_arch = "wasm32")]
     {
-        wasm::save(&location.key(), data.as_ref())
+        wasm::save(location.scope, &location.key(), data.as_ref())
     }
     #[cfg(not(target_arch = "wasm32"))]
     {
         let path = location.path()?;
         std::fs::create_dir_all(
-            &path
-                .parent()
+            path.parent()
                 .ok_or_else(|| anyhow!("Couldn't get parent of {:?}", &path))?,
         )?;
 
@@ -107,64 +212,209 @@ pub fn save_to<T: AsRef<[u8]>>(data: T, location: &Location) -> anyhow::Result<(
 
 /// Load some data from the default location
 pub fn load() -> anyhow::Result<Vec<u8>> {
+    load_from(&Location::default())
+}
+
+/// Load some data from the given location.
+///
+/// This reads data zipped on either platform (so saves copied between the web and desktop still load),
+/// but if it isn't zipped at all that's an error, since it's probably a corrupt or hand-edited save.
+/// To read uncompressed data too (like from an old build of your game), use [`load_detected_from`].
+pub fn load_from(location: &Location) -> anyhow::Result<Vec<u8>> {
+    match load_detected_from(location)? {
+        (_, Format::Raw) => Err(anyhow::anyhow!(
+            "The data isn't zipped, so it's probably corrupt (use `load_detected_from` to load it anyway)"
+        )),
+        (data, _) => Ok(data),
+    }
+}
+
+/// Load some data from the given location, and say what format it was found in.
+///
+/// Unlike [`load_from`], this is fine with data that isn't zipped at all (say, it was written by
+/// an old build of your game that didn't compress anything) and returns it as [`Format::Raw`].
+/// See [`unzip_any`](flate::unzip_any) for the details.
+///
+/// If the format isn't [current](Format::is_current), you might want to [`save_to`]
+/// the data again to bring it up to date.
+pub fn load_detected_from(location: &Location) -> anyhow::Result<(Vec<u8>, Format)> {
+    transaction::recover(&location.bin_name, location.scope)?;
+
     #[cfg(target_arch = "wasm32")]
     {
-        wasm::load(&Location::default().key())
+        wasm::load(location.scope, &location.key())
     }
     #[cfg(not(target_arch = "wasm32"))]
     {
-        load_from(&Location::default())
+        let zipped = std::fs::read(location.path()?).context("When reading the file")?;
+        unzip_any(&zipped)
     }
 }
 
-/// Load some data from the given location.
-pub fn load_from(location: &Location) -> anyhow::Result<Vec<u8>> {
+/// Check if there's any data saved at the given location.
+pub fn exists(location: &Location) -> anyhow::Result<bool> {
+    transaction::recover(&location.bin_name, location.scope)?;
+
     #[cfg(target_arch = "wasm32")]
     {
-        wasm::load(&location.key())
+        Ok(wasm::get_item(location.scope, &location.key())?.is_some())
     }
     #[cfg(not(target_arch = "wasm32"))]
     {
-        let zipped = std::fs::read(location.path()?).context("When reading the file")?;
-        unzip(&zipped)
+        Ok(location.path()?.exists())
+    }
+}
+
+/// Ask the browser not to throw away our data when it's running low on space.
+///
+/// Browsers are allowed to clear out localstorage for sites the player doesn't visit much,
+/// which is bad news for long-term saves. This goes through `navigator.storage.persist()`,
+/// and resolves to whether the data is now persistent. The browser might ask the player
+/// first, or just decide on its own.
+///
+/// On desktop (and on browsers that don't support asking), this resolves to whether
+/// [`is_persisted`] would.
+pub fn request_persistence() -> Waiter<bool> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::request_persistence()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Waiter::new_immediate(true)
+    }
+}
+
+/// Check if the browser has promised not to throw away our data.
+///
+/// On desktop, nobody is going to delete your data dir, so this is always `true`.
+/// On browsers that don't support checking, this is `false`.
+pub fn is_persisted() -> Waiter<bool> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::is_persisted()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Waiter::new_immediate(true)
     }
 }
 
 #[cfg(target_arch = "wasm32")]
 mod wasm {
-    use super::flate::zip64;
-    use crate::{js_convert::FromJsObject, storage::flate::unzip64};
+    use super::{
+        flate::{unzip_any, zip64, Format},
+        Scope,
+    };
+    use crate::js_convert::FromJsObject;
+
+    use crate::waiter::Waiter;
 
-    use anyhow::{anyhow, bail, Context};
+    use anyhow::{anyhow, Context};
     use sapp_jsutils::{JsObject, JsObjectWeak};
 
+    // If `session` is true, these use sessionStorage instead of localStorage.
     extern "C" {
         /// returns `Result<(), String>`
-        fn storage_save(key: JsObjectWeak, val: JsObjectWeak) -> JsObject;
-        /// returns `Result<String, String>`
-        fn storage_load(key: JsObjectWeak) -> JsObject;
+        fn storage_save(session: bool, key: JsObjectWeak, val: JsObjectWeak) -> JsObject;
+        /// returns `Result<Option<String>, String>`
+        fn storage_load(session: bool, key: JsObjectWeak) -> JsObject;
+        /// returns `Result<(), String>`
+        fn storage_remove(session: bool, key: JsObjectWeak) -> JsObject;
+        fn storage_persist() -> JsObject;
+        fn storage_persisted() -> JsObject;
+    }
+
+    pub fn save(scope: Scope, key: &str, val: &[u8]) -> anyhow::Result<()> {
+        set_item(scope, key, &zip64(val)?)
+    }
+    pub fn load(scope: Scope, key: &str) -> anyhow::Result<(Vec<u8>, Format)> {
+        let data = get_item(scope, key)?
+            .ok_or_else(|| anyhow!("Couldn't find key `{}` in {}", key, storage_name(scope)))?;
+        unzip_any(data)
+    }
+
+    fn storage_name(scope: Scope) -> &'static str {
+        match scope {
+            Scope::Persistent => "localstorage",
+            Scope::Session => "sessionstorage",
+        }
     }
 
-    pub fn save(key: &str, val: &[u8]) -> anyhow::Result<()> {
-        let key = JsObject::string(&key);
-        let val = JsObject::string(&zip64(val)?);
+    /// Put a raw string into localstorage or sessionstorage.
+    pub fn set_item(scope: Scope, key: &str, val: &str) -> anyhow::Result<()> {
+        let key = JsObject::string(key);
+        let val = JsObject::string(val);
 
-        let result = unsafe { storage_save(key.weak(), val.weak()) };
+        let result = unsafe { storage_save(scope == Scope::Session, key.weak(), val.weak()) };
         let result = Result::<(), String>::from_js(result)
             .context("When trying to turn the returned value into a Result")?;
-        match result {
-            Ok(()) => Ok(()),
-            Err(oh_no) => bail!(anyhow!(oh_no).context("When trying to save to localstorage")),
-        }
+        result.map_err(|e| {
+            anyhow!(e).context(format!("When trying to save to {}", storage_name(scope)))
+        })
+    }
+    /// Get a raw string out of localstorage or sessionstorage, or `None` if there's nothing there.
+    pub fn get_item(scope: Scope, key: &str) -> anyhow::Result<Option<String>> {
+        let key = JsObject::string(key);
+
+        let result = unsafe { storage_load(scope == Scope::Session, key.weak()) };
+        let result = Result::<Option<String>, String>::from_js(result)
+            .context("When trying to turn the returned value into a Result")?;
+        result.map_err(|e| {
+            anyhow!(e).context(format!("When trying to load from {}", storage_name(scope)))
+        })
     }
-    pub fn load(key: &str) -> anyhow::Result<Vec<u8>> {
-        let key = JsObject::string(&key);
+    /// Remove a key from localstorage or sessionstorage. It's fine if it wasn't there.
+    pub fn remove_item(scope: Scope, key: &str) -> anyhow::Result<()> {
+        let key = JsObject::string(key);
 
-        let result = unsafe { storage_load(key.weak()) };
-        let result = Result::<String, String>::from_js(result)
+        let result = unsafe { storage_remove(scope == Scope::Session, key.weak()) };
+        let result = Result::<(), String>::from_js(result)
             .context("When trying to turn the returned value into a Result")?;
-        let data =
-            result.map_err(|e| anyhow!(e).context("When trying to load from localstorage"))?;
-        unzip64(&data)
+        result.map_err(|e| {
+            anyhow!(e).context(format!(
+                "When trying to remove from {}",
+                storage_name(scope)
+            ))
+        })
+    }
+
+    pub fn request_persistence() -> Waiter<bool> {
+        Waiter::new_waiting(unsafe { storage_persist() })
+    }
+    pub fn is_persisted() -> Waiter<bool> {
+        Waiter::new_waiting(unsafe { storage_persisted() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_dir() {
+        let dir = Scope::Session.root().unwrap();
+        assert_eq!(Scope::Session.root().unwrap(), dir);
+        assert!(dir.is_dir());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+    }
+
+    #[test]
+    fn load_raw_only_when_asked() {
+        let location = Location::session("load_raw_only_when_asked");
+        save_to(b"zipped", &location).unwrap();
+        assert_eq!(load_from(&location).unwrap(), b"zipped");
+
+        std::fs::write(location.path().unwrap(), b"{\"legacy\":true}").unwrap();
+        assert!(load_from(&location).is_err());
+        assert_eq!(
+            load_detected_from(&location).unwrap(),
+            (b"{\"legacy\":true}".to_vec(), Format::Raw)
+        );
     }
 }
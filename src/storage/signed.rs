@@ -0,0 +1,148 @@
+//! Tamper-evident saves, signed with a key of your choosing.
+//!
+//! Gzip and base64 won't stop anyone with a browser console from giving themselves a million points.
+//! These functions tack an HMAC-SHA256 signature onto your data before it's saved, and check it when it's loaded,
+//! so edited saves fail to load with a [`Tampered`] error.
+//!
+//! Note this is *not* encryption; anyone can still read your data, they just can't change it.
+//! And the key has to be in your game somewhere, so a determined cheater can dig it out and sign whatever they like.
+//! But it's a lot more effort than pasting a new number into localstorage.
+//!
+//! The key is all yours; nothing here touches any OS keychain, so it works exactly the same on the web and desktop.
+
+use super::Location;
+
+use anyhow::bail;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use std::fmt;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long the signature is.
+const SIGNATURE_LEN: usize = 32;
+
+/// Sign some data with the key.
+///
+/// The output is the 32-byte signature, followed by the data.
+pub fn sign<T: AsRef<[u8]>>(data: T, key: &[u8]) -> Vec<u8> {
+    let data = data.as_ref();
+    let mut out = Vec::with_capacity(SIGNATURE_LEN + data.len());
+    out.extend_from_slice(&mac(key, data).finalize().into_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+/// Check something made with [`sign`], and return the original data.
+///
+/// If the data was changed, or signed with a different key, this returns a [`Tampered`] error
+/// (wrapped in `anyhow`, so `downcast_ref` it to tell it apart from other errors).
+pub fn verify<T: AsRef<[u8]>>(signed: T, key: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let signed = signed.as_ref();
+    if signed.len() < SIGNATURE_LEN {
+        bail!(Tampered);
+    }
+    let (signature, data) = signed.split_at(SIGNATURE_LEN);
+    // This is constant-time, to be extra careful
+    if mac(key, data).verify_slice(signature).is_err() {
+        bail!(Tampered);
+    }
+    Ok(data.to_vec())
+}
+
+/// Sign some data and save it to the default location.
+///
+/// If this returns `Err` it's *not* guaranteed that we made no edits
+/// to the filesystem or localstorage.
+pub fn save<T: AsRef<[u8]>>(data: T, key: &[u8]) -> anyhow::Result<()> {
+    save_to(data, &Location::default(), key)
+}
+
+/// Sign some data and save it to the given location.
+///
+/// If this returns `Err` it's *not* guaranteed that we made no edits
+/// to the filesystem or localstorage.
+pub fn save_to<T: AsRef<[u8]>>(data: T, location: &Location, key: &[u8]) -> anyhow::Result<()> {
+    super::save_to(sign(data, key), location)
+}
+
+/// Load some signed data from the default location, and check the signature.
+pub fn load(key: &[u8]) -> anyhow::Result<Vec<u8>> {
+    load_from(&Location::default(), key)
+}
+
+/// Load some signed data from the given location, and check the signature.
+pub fn load_from(location: &Location, key: &[u8]) -> anyhow::Result<Vec<u8>> {
+    verify(super::load_from(location)?, key)
+}
+
+fn mac(key: &[u8], data: &[u8]) -> HmacSha256 {
+    // HMAC is fine with keys of any length
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data);
+    mac
+}
+
+/// Error due to signed data failing its signature check.
+///
+/// Either someone edited it, or it was signed with a different key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tampered;
+
+impl fmt::Display for Tampered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The signature didn't match; the data was edited or signed with a different key"
+        )
+    }
+}
+
+impl std::error::Error for Tampered {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"super secret key";
+
+    fn assert_tampered(res: anyhow::Result<Vec<u8>>) {
+        let err = res.unwrap_err();
+        assert_eq!(err.downcast_ref::<Tampered>(), Some(&Tampered));
+    }
+
+    #[test]
+    fn round_trip() {
+        for data in [&b""[..], b"{\"points\":12}"].iter() {
+            let signed = sign(data, KEY);
+            assert_eq!(signed.len(), SIGNATURE_LEN + data.len());
+            assert_eq!(&verify(&signed, KEY).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn edited() {
+        let signed = sign(b"{\"points\":12}", KEY);
+        // Both in the signature and in the data
+        for idx in [0, SIGNATURE_LEN - 1, SIGNATURE_LEN, signed.len() - 1].iter() {
+            let mut edited = signed.clone();
+            edited[*idx] ^= 1;
+            assert_tampered(verify(&edited, KEY));
+        }
+    }
+
+    #[test]
+    fn wrong_key() {
+        let signed = sign(b"{\"points\":12}", KEY);
+        assert_tampered(verify(&signed, b"some other key"));
+    }
+
+    #[test]
+    fn too_short() {
+        let signed = sign(b"", KEY);
+        for len in [0, 1, SIGNATURE_LEN - 1].iter() {
+            assert_tampered(verify(&signed[..*len], KEY));
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! Saving to several locations at once, all-or-nothing.
+//!
+//! If you save the world to one [`Location`] and the player's inventory to another, and the game
+//! dies in between, the two are out of sync. A [`Transaction`] makes sure either every write happens or none of them do.
+//!
+//! Here's how it works:
+//!
+//! 1. Every write is saved to a staging file (`{profile}.dat.staged`) or key (`"{bin_name}/v{version}/{profile}/staged"`).
+//! 2. A journal listing all the locations is written in one go, to `/path/to/datadir/{bin_name}/transaction.journal`
+//!    or under the key `"{bin_name}/transaction"` (or wherever the locations' [`Scope`] puts them). This is the point of no return.
+//! 3. Each staged write is moved into place, and then the journal is deleted.
+//!
+//! If the game dies before step 2, nothing happened. If it dies after, the next time anything under
+//! that `bin_name` and scope is saved or loaded, the journal is found and the rest of step 3 is finished first.
+//! So loads never see half a transaction.
+
+use super::{
+    log::{parse_records, push_record},
+    Location, Scope,
+};
+
+use anyhow::bail;
+
+/// A set of writes that get saved all together, or not at all.
+///
+/// Stage writes with [`Transaction::save_to`], and then [`Transaction::commit`] them.
+#[derive(Debug, Clone, Default)]
+pub struct Transaction {
+    writes: Vec<(Vec<u8>, Location)>,
+}
+
+impl Transaction {
+    /// Make a new, empty transaction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stage some data to be saved to the given location when this is committed.
+    ///
+    /// If you stage more than one write to the same location, the last one wins.
+    pub fn save_to<T: AsRef<[u8]>>(&mut self, data: T, location: &Location) -> &mut Self {
+        self.writes.push((data.as_ref().to_vec(), location.clone()));
+        self
+    }
+
+    /// Save everything that was staged.
+    ///
+    /// All the locations must have the same `bin_name` and scope, because that's where the journal goes.
+    ///
+    /// If this returns `Err`, either nothing was saved, or the journal got written and
+    /// everything will be saved the next time something under this `bin_name` and scope is saved or loaded.
+    pub fn commit(&self) -> anyhow::Result<()> {
+        let (bin_name, scope) = match self.writes.first() {
+            Some((_, location)) => (&location.bin_name, location.scope),
+            None => return Ok(()),
+        };
+        if let Some((_, oops)) = self
+            .writes
+            .iter()
+            .find(|(_, location)| &location.bin_name != bin_name || location.scope != scope)
+        {
+            bail!(
+                "All the locations in a transaction must have the same bin_name and scope, but found both {:?} ({:?}) and {:?} ({:?})",
+                bin_name,
+                scope,
+                &oops.bin_name,
+                oops.scope
+            );
+        }
+
+        // Make sure some older transaction doesn't get finished on top of us later
+        recover(bin_name, scope)?;
+
+        let staged = (|| {
+            let mut journal = Vec::new();
+            for (data, location) in self.writes.iter() {
+                stage(data, location)?;
+                push_record(&mut journal, location.version.as_bytes());
+                push_record(&mut journal, location.profile.as_bytes());
+            }
+            write_journal(bin_name, scope, &journal)
+        })();
+        if let Err(e) = staged {
+            // No journal, so nothing's going to use these
+            for (_, location) in self.writes.iter() {
+                let _ = unstage(location);
+            }
+            return Err(e);
+        }
+
+        recover(bin_name, scope)
+    }
+}
+
+/// If there's a journal under this `bin_name` and scope, finish moving everything in it into place.
+///
+/// This is safe to call over and over again if it fails partway through.
+pub(super) fn recover(bin_name: &str, scope: Scope) -> anyhow::Result<()> {
+    let journal = match read_journal(bin_name, scope)? {
+        Some(it) => it,
+        None => return Ok(()),
+    };
+    let (records, _) = parse_records(&journal);
+    for pair in records.chunks_exact(2) {
+        let location = Location {
+            bin_name: bin_name.to_string(),
+            version: String::from_utf8_lossy(&pair[0]).into_owned(),
+            profile: String::from_utf8_lossy(&pair[1]).into_owned(),
+            scope,
+        };
+        apply(&location)?;
+    }
+    remove_journal(bin_name, scope)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+use desktop::*;
+#[cfg(target_arch = "wasm32")]
+use wasm::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop {
+    use crate::storage::{flate::zip, Location, Scope};
+
+    use anyhow::{anyhow, Context};
+
+    use std::{io::ErrorKind, path::PathBuf};
+
+    fn journal_path(bin_name: &str, scope: Scope) -> anyhow::Result<PathBuf> {
+        Ok(scope.root()?.join(bin_name).join("transaction.journal"))
+    }
+
+    fn staged_path(location: &Location) -> anyhow::Result<PathBuf> {
+        Ok(location.path()?.with_extension("dat.staged"))
+    }
+
+    pub fn stage(data: &[u8], location: &Location) -> anyhow::Result<()> {
+        let path = staged_path(location)?;
+        std::fs::create_dir_all(
+            path.parent()
+                .ok_or_else(|| anyhow!("Couldn't get parent of {:?}", &path))?,
+        )?;
+        std::fs::write(&path, zip(data)?)
+            .with_context(|| anyhow!("When writing to the file at {:?}", &path))
+    }
+
+    /// Throw away the staged file, if it's there.
+    pub fn unstage(location: &Location) -> anyhow::Result<()> {
+        let path = staged_path(location)?;
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(e).with_context(|| anyhow!("When removing {:?}", &path))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Move the staged file into place, if it's still there.
+    pub fn apply(location: &Location) -> anyhow::Result<()> {
+        let staged = staged_path(location)?;
+        if staged.exists() {
+            let path = location.path()?;
+            std::fs::rename(&staged, &path)
+                .with_context(|| anyhow!("When moving {:?} to {:?}", &staged, &path))?;
+        }
+        Ok(())
+    }
+
+    pub fn write_journal(bin_name: &str, scope: Scope, journal: &[u8]) -> anyhow::Result<()> {
+        let path = journal_path(bin_name, scope)?;
+        // Write it somewhere else first so the journal shows up all at once
+        let tmp = path.with_extension("journal.tmp");
+        let res = std::fs::write(&tmp, journal).and_then(|()| std::fs::rename(&tmp, &path));
+        if res.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        res.with_context(|| anyhow!("When writing the journal at {:?}", &path))
+    }
+
+    pub fn read_journal(bin_name: &str, scope: Scope) -> anyhow::Result<Option<Vec<u8>>> {
+        let path = journal_path(bin_name, scope)?;
+        match std::fs::read(&path) {
+            Ok(it) => Ok(Some(it)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| anyhow!("When reading the journal at {:?}", &path)),
+        }
+    }
+
+    pub fn remove_journal(bin_name: &str, scope: Scope) -> anyhow::Result<()> {
+        let path = journal_path(bin_name, scope)?;
+        std::fs::remove_file(&path)
+            .with_context(|| anyhow!("When removing the journal at {:?}", &path))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use crate::storage::{
+        flate::zip64,
+        wasm::{get_item, remove_item, set_item},
+        Location, Scope,
+    };
+
+    use anyhow::Context;
+    use base64::URL_SAFE;
+
+    fn journal_key(bin_name: &str) -> String {
+        format!("{}/transaction", bin_name)
+    }
+
+    fn staged_key(location: &Location) -> String {
+        format!("{}/staged", location.key())
+    }
+
+    pub fn stage(data: &[u8], location: &Location) -> anyhow::Result<()> {
+        set_item(location.scope, &staged_key(location), &zip64(data)?)
+    }
+
+    /// Throw away the staged value, if it's there.
+    pub fn unstage(location: &Location) -> anyhow::Result<()> {
+        remove_item(location.scope, &staged_key(location))
+    }
+
+    /// Copy the staged value into place, if it's still there.
+    pub fn apply(location: &Location) -> anyhow::Result<()> {
+        let staged_key = staged_key(location);
+        if let Some(staged) = get_item(location.scope, &staged_key)? {
+            set_item(location.scope, &location.key(), &staged)?;
+            remove_item(location.scope, &staged_key)?;
+        }
+        Ok(())
+    }
+
+    /// Localstorage writes are all-at-once already.
+    pub fn write_journal(bin_name: &str, scope: Scope, journal: &[u8]) -> anyhow::Result<()> {
+        set_item(
+            scope,
+            &journal_key(bin_name),
+            &base64::encode_config(journal, URL_SAFE),
+        )
+    }
+
+    pub fn read_journal(bin_name: &str, scope: Scope) -> anyhow::Result<Option<Vec<u8>>> {
+        match get_item(scope, &journal_key(bin_name))? {
+            Some(it) => Ok(Some(
+                base64::decode_config(&it, URL_SAFE).context("When decoding base64")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    pub fn remove_journal(bin_name: &str, scope: Scope) -> anyhow::Result<()> {
+        remove_item(scope, &journal_key(bin_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{load_from, save_to};
+
+    fn location(bin_name: &str, profile: &str) -> Location {
+        Location {
+            bin_name: bin_name.to_string(),
+            ..Location::session(profile)
+        }
+    }
+
+    #[test]
+    fn recover_after_crash() {
+        let world = location("transaction_recover_after_crash", "world");
+        let inventory = location("transaction_recover_after_crash", "inventory");
+        save_to(b"old world", &world).unwrap();
+        save_to(b"old inventory", &inventory).unwrap();
+
+        // Do what `commit` does, but die after moving only the first one into place
+        let mut journal = Vec::new();
+        for (data, location) in [(b"new world", &world), (b"new stuff", &inventory)].iter() {
+            stage(*data, location).unwrap();
+            push_record(&mut journal, location.version.as_bytes());
+            push_record(&mut journal, location.profile.as_bytes());
+        }
+        write_journal(&world.bin_name, Scope::Session, &journal).unwrap();
+        apply(&world).unwrap();
+
+        assert_eq!(load_from(&inventory).unwrap(), b"new stuff");
+        assert_eq!(load_from(&world).unwrap(), b"new world");
+        assert!(read_journal(&world.bin_name, Scope::Session)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn commit() {
+        let world = location("transaction_commit", "world");
+        let inventory = location("transaction_commit", "inventory");
+        Transaction::new()
+            .save_to(b"world", &world)
+            .save_to(b"inventory", &inventory)
+            .commit()
+            .unwrap();
+        assert_eq!(load_from(&world).unwrap(), b"world");
+        assert_eq!(load_from(&inventory).unwrap(), b"inventory");
+    }
+
+    #[test]
+    fn failed_commit_cleans_up() {
+        let world = location("transaction_failed_commit_cleans_up", "world");
+        let inventory = location("transaction_failed_commit_cleans_up", "inventory");
+
+        // Put a directory where the journal gets written, so writing it fails
+        let root = Scope::Session.root().unwrap().join(&world.bin_name);
+        std::fs::create_dir_all(root.join("transaction.journal.tmp")).unwrap();
+
+        let res = Transaction::new()
+            .save_to(b"world", &world)
+            .save_to(b"inventory", &inventory)
+            .commit();
+        assert!(res.is_err());
+        for location in [&world, &inventory].iter() {
+            assert!(!location.path().unwrap().exists());
+            assert!(!location
+                .path()
+                .unwrap()
+                .with_extension("dat.staged")
+                .exists());
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Find out how much space your saves are taking up, so you can warn the player
+//! before localstorage fills up.
+
+use crate::waiter::Waiter;
+
+/// Get how much space everything stored under the given `bin_name` takes up.
+///
+/// - On desktop, this is the total size in bytes of all the files in `/path/to/datadir/{bin_name}/`.
+/// - On the web, this is the total length of all the localstorage keys starting with `"{bin_name}/"`
+///   and their values. Browsers measure their localstorage limit (usually around 5 million) in
+///   the same units, so you can compare them directly.
+///
+/// This counts every version and profile, and [logs](super::log) too.
+/// It only counts [`Scope::Persistent`] data, though.
+///
+/// [`Scope::Persistent`]: super::Scope::Persistent
+pub fn bytes_used(bin_name: &str) -> anyhow::Result<u64> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::bytes_used(&format!("{}/", bin_name))
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use anyhow::anyhow;
+
+        let root = dirs::data_dir().ok_or_else(|| anyhow!("Couldn't find data dir!"))?;
+        let root = root.join(bin_name);
+        if root.exists() {
+            desktop::dir_size(&root)
+        } else {
+            Ok(0)
+        }
+    }
+}
+
+/// Ask the browser how much space it thinks this site is using, and how much it's allowed to use.
+///
+/// This goes through `navigator.storage.estimate()`, which covers all the storage the site uses
+/// (not just localstorage), and might be deliberately imprecise.
+///
+/// On desktop, or on browsers that don't support it, this resolves to `None`.
+pub fn estimate() -> Waiter<Option<QuotaEstimate>> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        wasm::estimate()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        Waiter::new_immediate(None)
+    }
+}
+
+/// The browser's guess at how much storage is being used.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuotaEstimate {
+    /// How many bytes the site is using
+    pub usage: f64,
+    /// How many bytes the site is allowed to use
+    pub quota: f64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod desktop {
+    use std::path::Path;
+
+    pub fn dir_size(dir: &Path) -> anyhow::Result<u64> {
+        let mut total = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            total += if meta.is_dir() {
+                dir_size(&entry.path())?
+            } else {
+                meta.len()
+            };
+        }
+        Ok(total)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use super::QuotaEstimate;
+    use crate::{js_convert::FromJsObject, objecttools::ObjectTools, waiter::Waiter};
+
+    use anyhow::{anyhow, Context};
+    use sapp_jsutils::{JsObject, JsObjectWeak};
+
+    extern "C" {
+        /// returns `Result<number, String>`
+        fn storage_usage(prefix: JsObjectWeak) -> JsObject;
+        fn storage_estimate() -> JsObject;
+    }
+
+    pub fn bytes_used(prefix: &str) -> anyhow::Result<u64> {
+        let prefix = JsObject::string(prefix);
+
+        let result = unsafe { storage_usage(prefix.weak()) };
+        let result = Result::<f64, String>::from_js(result)
+            .context("When trying to turn the returned value into a Result")?;
+        let used = result.map_err(|e| anyhow!(e).context("When trying to measure localstorage"))?;
+        Ok(used as u64)
+    }
+
+    pub fn estimate() -> Waiter<Option<QuotaEstimate>> {
+        Waiter::new_waiting(unsafe { storage_estimate() })
+    }
+
+    impl FromJsObject for QuotaEstimate {
+        type Error = anyhow::Error;
+
+        fn from_js(obj: JsObject) -> Result<Self, Self::Error> {
+            let get = |field| -> anyhow::Result<f64> {
+                let it = obj
+                    .try_get_field(field)
+                    .ok_or_else(|| anyhow!("Couldn't find `{}` field", field))?;
+                Ok(f64::from_js(it)?)
+            };
+            Ok(Self {
+                usage: get("usage")?,
+                quota: get("quota")?,
+            })
+        }
+    }
+}
@@ -4,7 +4,7 @@ use crate::js_convert::MaybeFromJsObject;
 
 use std::fmt::{self, Debug};
 
-/// Something that is waiting on a value from Javascript,
+/// Something that is waiting on a value from Javascript or a desktop worker thread,
 /// or has the value immediately via a desktop API.
 ///
 /// This struct was made for the Clipboard API, so it might be ill-suited
@@ -16,19 +16,52 @@ pub struct Waiter<T> {
 }
 
 impl<T: MaybeFromJsObject> Waiter<T> {
+    /// Make a new Waiter from a JsObject returned from `waitify`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn new_waiting(waiter: sapp_jsutils::JsObject) -> Self {
+        Self::new_waiting_with(waiter, |value| {
+            T::from_js(value).map_err(|e| {
+                let err: Box<_> = e.into();
+                err.to_string()
+            })
+        })
+    }
+}
+
+impl<T> Waiter<T> {
     /// Make a new Waiter with an immediate value.
-    #[cfg(not(target_arch = "wasm32"))]
+    ///
+    /// On the web this is handy for errors you know about before even asking JS.
     pub fn new_immediate(val: T) -> Self {
         Self {
             inner: WaiterInner::Available(val),
         }
     }
 
-    /// Make a new Waiter from a JsObject returned from `waitify`.
+    /// Make a new Waiter that gets its value from a channel, like from a worker thread.
+    ///
+    /// If the sender is dropped without sending anything, this will never return Some.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_receiving(receiver: std::sync::mpsc::Receiver<T>) -> Self {
+        Self {
+            inner: WaiterInner::Receiving(receiver),
+        }
+    }
+
+    /// Make a new Waiter from a JsObject returned from `waitify`,
+    /// turning the value into a `T` with the given function instead of [`FromJsObject`].
+    ///
+    /// This is handy when `T` doesn't (or can't) implement `FromJsObject`,
+    /// like getting a `Vec<u8>` out of a `Uint8Array`.
+    ///
+    /// [`FromJsObject`]: crate::js_convert::FromJsObject
     #[cfg(target_arch = "wasm32")]
-    pub fn new_waiting(waiter: sapp_jsutils::JsObject) -> Self {
+    pub fn new_waiting_with(
+        waiter: sapp_jsutils::JsObject,
+        convert: fn(sapp_jsutils::JsObject) -> Result<T, String>,
+    ) -> Self {
         Self {
-            inner: WaiterInner::Waiting(waiter, std::marker::PhantomData),
+            inner: WaiterInner::Waiting(waiter, convert),
         }
     }
 
@@ -39,6 +72,20 @@ impl<T: MaybeFromJsObject> Waiter<T> {
         }
     }
 
+    /// Check if this is done, and [`try_get`](Waiter::try_get) will never return `Some` again.
+    ///
+    /// That's either because the value was already taken, or because something went wrong
+    /// (like the conversion from JS failing, or the desktop worker going away without sending anything)
+    /// and it's never going to show up. It's only noticed once `try_get` runs into it.
+    pub fn is_finished(&self) -> bool {
+        match &self.inner {
+            WaiterInner::Taken => true,
+            #[cfg(target_arch = "wasm32")]
+            WaiterInner::Error(..) => true,
+            _ => false,
+        }
+    }
+
     /// Try and get the value from this.
     ///
     /// You shouldn't write your code relying on this to return anytime soon.
@@ -59,7 +106,6 @@ impl<T: MaybeFromJsObject> Waiter<T> {
                 None
             }
 
-            #[cfg(not(target_arch = "wasm32"))]
             WaiterInner::Available(_) => {
                 // entry api when
                 let taken = std::mem::replace(&mut self.inner, WaiterInner::Taken);
@@ -70,8 +116,27 @@ impl<T: MaybeFromJsObject> Waiter<T> {
                 Some(taken)
             }
 
+            #[cfg(not(target_arch = "wasm32"))]
+            WaiterInner::Receiving(receiver) => {
+                use std::sync::mpsc::TryRecvError;
+
+                match receiver.try_recv() {
+                    Ok(it) => {
+                        self.inner = WaiterInner::Taken;
+                        Some(it)
+                    }
+                    // not yet
+                    Err(TryRecvError::Empty) => None,
+                    // never
+                    Err(TryRecvError::Disconnected) => {
+                        self.inner = WaiterInner::Taken;
+                        None
+                    }
+                }
+            }
+
             #[cfg(target_arch = "wasm32")]
-            WaiterInner::Waiting(waiter, _phantom) => {
+            WaiterInner::Waiting(waiter, convert) => {
                 use crate::objecttools::ObjectTools;
 
                 let res: Result<Option<T>, String> = (|| {
@@ -86,10 +151,7 @@ impl<T: MaybeFromJsObject> Waiter<T> {
                         let value = waiter
                             .try_get_field("value")
                             .ok_or_else(|| "Couldn't find `value` field".to_string())?;
-                        let value = T::from_js(value).map_err(|e| {
-                            let err: Box<_> = e.into();
-                            err.to_string()
-                        })?;
+                        let value = convert(value)?;
                         // nice!
                         Some(value)
                     })
@@ -122,16 +184,20 @@ enum WaiterInner<T> {
     Taken,
 
     /// The value is immediately available.
-    #[cfg(not(target_arch = "wasm32"))]
     Available(T),
+    /// On desktop, something else is working on it.
+    #[cfg(not(target_arch = "wasm32"))]
+    Receiving(std::sync::mpsc::Receiver<T>),
     /// On the web, we wait.
     ///
     /// I hate waiting.
     ///
-    /// Must have the phantom data here because otherwise the T isn't used anywhere in the type
-    /// on wasm.
+    /// The function turns the JS value into a `T` once it shows up.
     #[cfg(target_arch = "wasm32")]
-    Waiting(sapp_jsutils::JsObject, std::marker::PhantomData<T>),
+    Waiting(
+        sapp_jsutils::JsObject,
+        fn(sapp_jsutils::JsObject) -> Result<T, String>,
+    ),
     /// An error occurred somewhere.
     /// And here's your error!
     #[cfg(target_arch = "wasm32")]
@@ -143,8 +209,9 @@ impl<T: Debug> Debug for WaiterInner<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Taken => write!(f, "Taken"),
-            #[cfg(not(target_arch = "wasm32"))]
             Self::Available(it) => write!(f, "Available({:?})", it),
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Receiving(_) => write!(f, "Receiving"),
             #[cfg(target_arch = "wasm32")]
             WaiterInner::Waiting(_, _) => write!(f, "Waiting"),
             #[cfg(target_arch = "wasm32")]
@@ -152,3 +219,37 @@ impl<T: Debug> Debug for WaiterInner<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn immediate() {
+        let mut waiter = Waiter::new_immediate(5);
+        assert!(!waiter.is_finished());
+        assert_eq!(waiter.try_get(), Some(5));
+        assert!(waiter.is_finished());
+        assert_eq!(waiter.try_get(), None);
+    }
+
+    #[test]
+    fn receiving() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut waiter = Waiter::new_receiving(rx);
+        assert_eq!(waiter.try_get(), None);
+        assert!(!waiter.is_finished());
+        tx.send(5).unwrap();
+        assert_eq!(waiter.try_get(), Some(5));
+        assert!(waiter.is_finished());
+    }
+
+    #[test]
+    fn receiving_dropped() {
+        let (tx, rx) = std::sync::mpsc::channel::<i32>();
+        let mut waiter = Waiter::new_receiving(rx);
+        drop(tx);
+        assert_eq!(waiter.try_get(), None);
+        assert!(waiter.is_finished());
+    }
+}